@@ -1,106 +1,155 @@
 use crate::render::lib::Vertex;
 
+/// White so instances show their tint and texture unchanged.
 pub const CUBE: &[Vertex] = &[
     Vertex {
         position: [-1.0, -1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.0, 1.0],
     },
     Vertex {
         position: [1.0, -1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [1.0, 1.0],
     },
     Vertex {
         position: [1.0, 1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [1.0, 0.0],
     },
     Vertex {
         position: [-1.0, 1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.0, 0.0],
     },
     // bottom (0, 0, -1.0)
     Vertex {
         position: [-1.0, 1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 0.0, -1.0],
+        tex_coords: [0.0, 1.0],
     },
     Vertex {
         position: [1.0, 1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 0.0, -1.0],
+        tex_coords: [1.0, 1.0],
     },
     Vertex {
         position: [1.0, -1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 0.0, -1.0],
+        tex_coords: [1.0, 0.0],
     },
     Vertex {
         position: [-1.0, -1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 0.0, -1.0],
+        tex_coords: [0.0, 0.0],
     },
     // right (1.0, 0, 0)
     Vertex {
         position: [1.0, -1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [1.0, 0.0, 0.0],
+        tex_coords: [0.0, 1.0],
     },
     Vertex {
         position: [1.0, 1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [1.0, 0.0, 0.0],
+        tex_coords: [1.0, 1.0],
     },
     Vertex {
         position: [1.0, 1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [1.0, 0.0, 0.0],
+        tex_coords: [1.0, 0.0],
     },
     Vertex {
         position: [1.0, -1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [1.0, 0.0, 0.0],
+        tex_coords: [0.0, 0.0],
     },
     // left (-1.0, 0, 0)
     Vertex {
         position: [-1.0, -1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [-1.0, 0.0, 0.0],
+        tex_coords: [0.0, 1.0],
     },
     Vertex {
         position: [-1.0, 1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [-1.0, 0.0, 0.0],
+        tex_coords: [1.0, 1.0],
     },
     Vertex {
         position: [-1.0, 1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [-1.0, 0.0, 0.0],
+        tex_coords: [1.0, 0.0],
     },
     Vertex {
         position: [-1.0, -1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [-1.0, 0.0, 0.0],
+        tex_coords: [0.0, 0.0],
     },
     // front (0, 1.0, 0)
     Vertex {
         position: [1.0, 1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 1.0, 0.0],
+        tex_coords: [0.0, 1.0],
     },
     Vertex {
         position: [-1.0, 1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 1.0, 0.0],
+        tex_coords: [1.0, 1.0],
     },
     Vertex {
         position: [-1.0, 1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 1.0, 0.0],
+        tex_coords: [1.0, 0.0],
     },
     Vertex {
         position: [1.0, 1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, 1.0, 0.0],
+        tex_coords: [0.0, 0.0],
     },
     // back (0, -1.0, 0)
     Vertex {
         position: [1.0, -1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, -1.0, 0.0],
+        tex_coords: [0.0, 1.0],
     },
     Vertex {
         position: [-1.0, -1.0, 1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, -1.0, 0.0],
+        tex_coords: [1.0, 1.0],
     },
     Vertex {
         position: [-1.0, -1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, -1.0, 0.0],
+        tex_coords: [1.0, 0.0],
     },
     Vertex {
         position: [1.0, -1.0, -1.0],
-        color: [1.0, 0.0, 0.0],
+        color: [1.0, 1.0, 1.0],
+        normal: [0.0, -1.0, 0.0],
+        tex_coords: [0.0, 0.0],
     },
 ];
 
@@ -117,39 +166,57 @@ pub const TRIANGLE: &[Vertex] = &[
     Vertex {
         position: [-0.0868241, 0.49240386, 0.0],
         color: [0.5, 0.0, 0.5],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.4131759, 0.0075961],
     }, // A
     Vertex {
         position: [-0.49513406, 0.06958647, 0.0],
         color: [0.5, 0.0, 0.5],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.0048659, 0.4304135],
     }, // B
     Vertex {
         position: [0.44147372, 0.2347359, 0.0],
         color: [0.5, 0.0, 0.5],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.9414737, 0.2652641],
     }, // E
     Vertex {
         position: [-0.49513406, 0.06958647, 0.0],
         color: [0.5, 0.0, 0.5],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.0048659, 0.4304135],
     }, // B
     Vertex {
         position: [-0.21918549, -0.44939706, 0.0],
         color: [0.5, 0.0, 0.5],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.2808145, 0.9493971],
     }, // C
     Vertex {
         position: [0.44147372, 0.2347359, 0.0],
         color: [0.5, 0.0, 0.5],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.9414737, 0.2652641],
     }, // E
     Vertex {
         position: [-0.21918549, -0.44939706, 0.0],
         color: [0.5, 0.0, 0.5],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.2808145, 0.9493971],
     }, // C
     Vertex {
         position: [0.35966998, -0.3473291, 0.0],
         color: [0.5, 0.0, 0.5],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.85967, 0.8473291],
     }, // D
     Vertex {
         position: [0.44147372, 0.2347359, 0.0],
         color: [0.5, 0.0, 0.5],
+        normal: [0.0, 0.0, 1.0],
+        tex_coords: [0.9414737, 0.2652641],
     }, // E
 ];
 
-pub const TRIANGLE_INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4, /* padding */ 0];
+pub const TRIANGLE_INDICES: &[u16] = &[0, 1, 4, 1, 2, 4, 2, 3, 4];
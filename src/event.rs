@@ -1,80 +1,865 @@
-use std::sync::{Arc, Mutex};
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
 
+use crate::render::instance::InstanceHandle;
 use crate::RenderState;
+use cgmath::Point3;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
 use winit::event::*;
 use winit::window::WindowId;
 
+/// Changes to the engine published by listeners, queued with
+/// `EngineEventQueue::publish` and delivered to the engine observers once per frame.
+#[derive(Clone, Debug, PartialEq)]
 pub enum EngineEvent {
-    KeyPress,
+    InstanceSpawned {
+        handle: InstanceHandle,
+    },
+    InstanceRemoved {
+        handle: InstanceHandle,
+    },
+    /// The camera ended a simulation step somewhere else than it started.
+    CameraMoved {
+        eye: Point3<f32>,
+        target: Point3<f32>,
+    },
 }
 
-pub struct EngineChange {
-    pub(crate) event: EngineEvent,
+/// The type of an `EngineEvent` without its data, engine observers choose which
+/// kinds they are notified of.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EngineEventKind {
+    InstanceSpawned,
+    InstanceRemoved,
+    CameraMoved,
 }
 
+impl EngineEvent {
+    pub fn kind(&self) -> EngineEventKind {
+        match self {
+            EngineEvent::InstanceSpawned { .. } => EngineEventKind::InstanceSpawned,
+            EngineEvent::InstanceRemoved { .. } => EngineEventKind::InstanceRemoved,
+            EngineEvent::CameraMoved { .. } => EngineEventKind::CameraMoved,
+        }
+    }
+}
+
+/// State the engine events of a frame are queued on until the engine observers are
+/// notified, the engine's is `RenderState`.
+pub trait EngineEventQueue {
+    fn engine_events(&mut self) -> &mut Vec<EngineEvent>;
+
+    /// Queues `event` for the engine observers, they see it after this frame's
+    /// simulation steps.
+    fn publish(&mut self, event: EngineEvent) {
+        self.engine_events().push(event);
+    }
+}
+
+/// Priority of input observers added without one.
+pub const DEFAULT_PRIORITY: i32 = 0;
+
+/// Handle of an observer registered as `O`, e.g. `Subscription<dyn UpdateObserver>`,
+/// used to remove, disable or enable it again.
+pub struct Subscription<O: ?Sized> {
+    id: u64,
+    kind: PhantomData<fn(&O)>,
+}
+
+impl<O: ?Sized> Clone for Subscription<O> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<O: ?Sized> Copy for Subscription<O> {}
+
+impl<O: ?Sized> PartialEq for Subscription<O> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<O: ?Sized> Eq for Subscription<O> {}
+
+impl<O: ?Sized> fmt::Debug for Subscription<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Subscription").field(&self.id).finish()
+    }
+}
+
+/// An observer and its priority, observers with a higher priority are notified first.
+struct Entry<O: ?Sized> {
+    id: u64,
+    priority: i32,
+    enabled: bool,
+    observer: Arc<Mutex<O>>,
+}
+
+/// The observers of one kind, ordered by priority.
+pub struct ObserverList<O: ?Sized> {
+    next_id: u64,
+    entries: Vec<Entry<O>>,
+}
+
+/// Cloning shares the observers. Observers are `Send` to keep the clones shareable.
+#[derive(Clone)]
 pub struct EventSystem {
-    update_observers: Vec<Arc<Mutex<dyn UpdateObserver>>>,
-    input_observers: Vec<Arc<Mutex<dyn InputObserver>>>,
+    update_observers: Arc<Mutex<ObserverList<dyn UpdateObserver>>>,
+    input_observers: Arc<Mutex<ObserverList<dyn InputObserver>>>,
+    window_observers: Arc<Mutex<ObserverList<dyn WindowObserver>>>,
+    engine_observers: Arc<Mutex<ObserverList<dyn EngineObserver>>>,
+}
+
+/// Refers to an `EventSystem` without keeping its observers alive. Listeners that
+/// change subscriptions from within their callbacks keep one of these, a clone of the
+/// system they are subscribed to would never let either be dropped.
+#[derive(Clone)]
+pub struct WeakEventSystem {
+    update_observers: Weak<Mutex<ObserverList<dyn UpdateObserver>>>,
+    input_observers: Weak<Mutex<ObserverList<dyn InputObserver>>>,
+    window_observers: Weak<Mutex<ObserverList<dyn WindowObserver>>>,
+    engine_observers: Weak<Mutex<ObserverList<dyn EngineObserver>>>,
+}
+
+impl WeakEventSystem {
+    /// The event system, or `None` once every clone of it was dropped.
+    pub fn upgrade(&self) -> Option<EventSystem> {
+        Some(EventSystem {
+            update_observers: self.update_observers.upgrade()?,
+            input_observers: self.input_observers.upgrade()?,
+            window_observers: self.window_observers.upgrade()?,
+            engine_observers: self.engine_observers.upgrade()?,
+        })
+    }
+}
+
+/// Observer traits the event system keeps a list for.
+pub trait ObserverKind {
+    fn observers(event_system: &EventSystem) -> &Mutex<ObserverList<Self>>;
+}
+
+impl ObserverKind for dyn UpdateObserver {
+    fn observers(event_system: &EventSystem) -> &Mutex<ObserverList<Self>> {
+        &event_system.update_observers
+    }
+}
+
+impl ObserverKind for dyn InputObserver {
+    fn observers(event_system: &EventSystem) -> &Mutex<ObserverList<Self>> {
+        &event_system.input_observers
+    }
+}
+
+impl ObserverKind for dyn WindowObserver {
+    fn observers(event_system: &EventSystem) -> &Mutex<ObserverList<Self>> {
+        &event_system.window_observers
+    }
+}
+
+impl ObserverKind for dyn EngineObserver {
+    fn observers(event_system: &EventSystem) -> &Mutex<ObserverList<Self>> {
+        &event_system.engine_observers
+    }
 }
 
 pub struct EventMatcher {}
 
 impl EventMatcher {
+    /// Dispatches `event` to the observers, returns true if an input observer handled it.
     pub fn on_event(
         event_window_id: &WindowId,
         event: &Event<()>,
         event_system: &EventSystem,
         state: &mut RenderState,
-    ) {
+    ) -> bool {
         match event {
             Event::RedrawRequested(_) => {
-                event_system.notify_update(state);
+                let delta = state.frame_clock.tick();
+                event_system.run_simulation(delta, state);
+                event_system.notify_engine_events(state);
             }
-            Event::WindowEvent { window_id, event } if event_window_id == window_id => {
-                if let WindowEvent::KeyboardInput { input, .. } = event {
-                    event_system.notify_keyboard_input(input, state);
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::KeyboardInput { input, .. },
+            } if event_window_id == window_id => {
+                // Held keys are tracked even when an observer handles the press
+                if let Some(code) = input.virtual_keycode {
+                    state
+                        .key_state
+                        .on_key_change(code, input.state == ElementState::Pressed);
                 }
+                return event_system.notify_keyboard_input(input, state);
+            }
+            Event::WindowEvent {
+                window_id,
+                event:
+                    WindowEvent::MouseInput {
+                        state: button_state,
+                        button,
+                        ..
+                    },
+            } if event_window_id == window_id => {
+                return event_system.notify_mouse_button(*button, *button_state, state);
+            }
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::MouseWheel { delta, .. },
+            } if event_window_id == window_id => {
+                return event_system.notify_mouse_wheel(delta, state);
+            }
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::CursorMoved { position, .. },
+            } if event_window_id == window_id => {
+                return event_system.notify_cursor_moved(*position, state);
+            }
+            // The render state is resized first, so observers already see the new size
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::Resized(size),
+            } if event_window_id == window_id => {
+                state.resize(*size);
+                event_system.notify_resize(*size, state);
+            }
+            Event::WindowEvent {
+                window_id,
+                event:
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
+                    },
+            } if event_window_id == window_id => {
+                state.resize(**new_inner_size);
+                event_system.notify_scale_factor_change(*scale_factor, **new_inner_size, state);
+            }
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::Focused(focused),
+            } if event_window_id == window_id => {
+                event_system.notify_focus_change(*focused, state);
+            }
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::DroppedFile(path),
+            } if event_window_id == window_id => {
+                event_system.notify_file_drop(path, state);
+            }
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::CloseRequested,
+            } if event_window_id == window_id => {
+                event_system.notify_close_requested(state);
+            }
+            // Raw device motion keeps coming when the cursor is grabbed at the window edge
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => {
+                return event_system.notify_mouse_motion(*delta, state);
             }
             _ => {}
         }
+        false
     }
 }
 
 impl EventSystem {
     pub fn new() -> EventSystem {
         EventSystem {
-            update_observers: vec![],
-            input_observers: vec![],
+            update_observers: Arc::new(Mutex::new(ObserverList::new())),
+            input_observers: Arc::new(Mutex::new(ObserverList::new())),
+            window_observers: Arc::new(Mutex::new(ObserverList::new())),
+            engine_observers: Arc::new(Mutex::new(ObserverList::new())),
         }
     }
 
-    pub fn notify_update(&self, state: &mut RenderState) {
-        for observer in self.update_observers.clone() {
-            let mut observer = observer.lock().unwrap();
-            observer.on_update(state);
+    pub fn downgrade(&self) -> WeakEventSystem {
+        WeakEventSystem {
+            update_observers: Arc::downgrade(&self.update_observers),
+            input_observers: Arc::downgrade(&self.input_observers),
+            window_observers: Arc::downgrade(&self.window_observers),
+            engine_observers: Arc::downgrade(&self.engine_observers),
         }
     }
 
-    pub fn notify_keyboard_input(&self, input: &KeyboardInput, state: &mut RenderState) {
-        for observer in self.input_observers.clone() {
-            let mut observer = observer.lock().unwrap();
-            observer.on_input_change(input, state);
+    /// Runs as many fixed simulation steps as fit into a frame of `delta` seconds, the
+    /// update observers see every step with the same delta.
+    pub fn run_simulation(&self, delta: f32, state: &mut RenderState) {
+        let steps = state.timestep.advance(delta);
+        for _ in 0..steps {
+            state.camera.begin_step();
+            self.notify_update(state.timestep.step(), state);
         }
     }
 
-    pub fn add_update_observer(&mut self, observer: Arc<Mutex<dyn UpdateObserver>>) {
-        self.update_observers.push(observer);
+    pub fn notify_update(&self, delta: f32, state: &mut RenderState) {
+        dispatch(&self.update_observers, |observer| {
+            observer.on_update(delta, state);
+            false
+        });
+    }
+
+    pub fn notify_keyboard_input(&self, input: &KeyboardInput, state: &mut RenderState) -> bool {
+        dispatch(&self.input_observers, |observer| {
+            observer.on_input_change(input, state)
+        })
+    }
+
+    pub fn notify_mouse_motion(&self, delta: (f64, f64), state: &mut RenderState) -> bool {
+        dispatch(&self.input_observers, |observer| {
+            observer.on_mouse_motion(delta, state)
+        })
+    }
+
+    pub fn notify_mouse_button(
+        &self,
+        button: MouseButton,
+        button_state: ElementState,
+        state: &mut RenderState,
+    ) -> bool {
+        dispatch(&self.input_observers, |observer| {
+            observer.on_mouse_button(button, button_state, state)
+        })
+    }
+
+    pub fn notify_mouse_wheel(&self, delta: &MouseScrollDelta, state: &mut RenderState) -> bool {
+        dispatch(&self.input_observers, |observer| {
+            observer.on_mouse_wheel(delta, state)
+        })
+    }
+
+    pub fn notify_cursor_moved(
+        &self,
+        position: PhysicalPosition<f64>,
+        state: &mut RenderState,
+    ) -> bool {
+        dispatch(&self.input_observers, |observer| {
+            observer.on_cursor_moved(position, state)
+        })
+    }
+
+    pub fn notify_resize(&self, size: PhysicalSize<u32>, state: &mut RenderState) {
+        dispatch(&self.window_observers, |observer| {
+            observer.on_resize(size, state);
+            false
+        });
+    }
+
+    pub fn notify_scale_factor_change(
+        &self,
+        scale_factor: f64,
+        size: PhysicalSize<u32>,
+        state: &mut RenderState,
+    ) {
+        dispatch(&self.window_observers, |observer| {
+            observer.on_scale_factor_change(scale_factor, size, state);
+            false
+        });
+    }
+
+    pub fn notify_focus_change(&self, focused: bool, state: &mut RenderState) {
+        dispatch(&self.window_observers, |observer| {
+            observer.on_focus_change(focused, state);
+            false
+        });
+    }
+
+    pub fn notify_file_drop(&self, path: &Path, state: &mut RenderState) {
+        dispatch(&self.window_observers, |observer| {
+            observer.on_file_drop(path, state);
+            false
+        });
     }
 
-    pub fn add_input_observer(&mut self, observer: Arc<Mutex<dyn InputObserver>>) {
-        self.input_observers.push(observer);
+    pub fn notify_close_requested(&self, state: &mut RenderState) {
+        dispatch(&self.window_observers, |observer| {
+            observer.on_close_requested(state);
+            false
+        });
+    }
+
+    /// Drains the queue of deferred engine events, called once per frame after the
+    /// simulation steps. Events published while they are delivered stay queued for
+    /// the next frame.
+    pub fn notify_engine_events(&self, state: &mut RenderState) {
+        deliver_engine_events(&self.engine_observers, state);
+    }
+
+    pub fn add_update_observer(
+        &self,
+        observer: Arc<Mutex<dyn UpdateObserver>>,
+    ) -> Subscription<dyn UpdateObserver> {
+        self.update_observers
+            .lock()
+            .unwrap()
+            .insert(observer, DEFAULT_PRIORITY)
+    }
+
+    pub fn add_input_observer(
+        &self,
+        observer: Arc<Mutex<dyn InputObserver>>,
+    ) -> Subscription<dyn InputObserver> {
+        self.add_input_observer_with_priority(observer, DEFAULT_PRIORITY)
+    }
+
+    /// Observers with a higher `priority` see input first and can keep it from the
+    /// rest by handling it, equal priorities are called in the order they were added.
+    pub fn add_input_observer_with_priority(
+        &self,
+        observer: Arc<Mutex<dyn InputObserver>>,
+        priority: i32,
+    ) -> Subscription<dyn InputObserver> {
+        self.input_observers
+            .lock()
+            .unwrap()
+            .insert(observer, priority)
+    }
+
+    pub fn add_window_observer(
+        &self,
+        observer: Arc<Mutex<dyn WindowObserver>>,
+    ) -> Subscription<dyn WindowObserver> {
+        self.window_observers
+            .lock()
+            .unwrap()
+            .insert(observer, DEFAULT_PRIORITY)
+    }
+
+    pub fn add_engine_observer(
+        &self,
+        observer: Arc<Mutex<dyn EngineObserver>>,
+    ) -> Subscription<dyn EngineObserver> {
+        self.engine_observers
+            .lock()
+            .unwrap()
+            .insert(observer, DEFAULT_PRIORITY)
+    }
+
+    /// Unsubscribes the observer, returns false if it was removed already. Safe to call
+    /// from within a callback, the observer isn't notified again from then on.
+    pub fn remove<O: ObserverKind + ?Sized>(&self, subscription: Subscription<O>) -> bool {
+        O::observers(self).lock().unwrap().remove(subscription.id)
+    }
+
+    /// Disabled observers keep their place but are skipped until they are enabled
+    /// again, returns false if the observer was removed.
+    pub fn set_enabled<O: ObserverKind + ?Sized>(
+        &self,
+        subscription: Subscription<O>,
+        enabled: bool,
+    ) -> bool {
+        O::observers(self)
+            .lock()
+            .unwrap()
+            .set_enabled(subscription.id, enabled)
     }
 }
 
-pub trait UpdateObserver {
-    fn on_update(&mut self, state: &mut RenderState);
+pub trait UpdateObserver: Send {
+    /// `delta` is the time since the previous update in seconds.
+    fn on_update(&mut self, delta: f32, state: &mut RenderState);
 }
 
-pub trait InputObserver {
-    fn on_input_change(&mut self, input: &KeyboardInput, state: &mut RenderState);
+/// Keyboard input has to be handled, the mouse handlers default to ignoring the event.
+/// Returning true marks the input as handled, observers with a lower priority won't
+/// see it.
+pub trait InputObserver: Send {
+    fn on_input_change(&mut self, input: &KeyboardInput, state: &mut RenderState) -> bool;
+
+    /// Raw mouse movement in device units, not bound to the cursor position.
+    fn on_mouse_motion(&mut self, _delta: (f64, f64), _state: &mut RenderState) -> bool {
+        false
+    }
+
+    fn on_mouse_button(
+        &mut self,
+        _button: MouseButton,
+        _button_state: ElementState,
+        _state: &mut RenderState,
+    ) -> bool {
+        false
+    }
+
+    fn on_mouse_wheel(&mut self, _delta: &MouseScrollDelta, _state: &mut RenderState) -> bool {
+        false
+    }
+
+    /// Cursor position in pixels from the top left corner of the window.
+    fn on_cursor_moved(
+        &mut self,
+        _position: PhysicalPosition<f64>,
+        _state: &mut RenderState,
+    ) -> bool {
+        false
+    }
+}
+
+/// Changes to the window itself, every handler defaults to ignoring the event.
+pub trait WindowObserver: Send {
+    /// Called after the render state was resized to `size`.
+    fn on_resize(&mut self, _size: PhysicalSize<u32>, _state: &mut RenderState) {}
+
+    /// The window moved to a monitor with a different DPI or the DPI setting changed,
+    /// the render state was resized to `size` already.
+    fn on_scale_factor_change(
+        &mut self,
+        _scale_factor: f64,
+        _size: PhysicalSize<u32>,
+        _state: &mut RenderState,
+    ) {
+    }
+
+    fn on_focus_change(&mut self, _focused: bool, _state: &mut RenderState) {}
+
+    /// Called once per file when several files are dropped onto the window.
+    fn on_file_drop(&mut self, _path: &Path, _state: &mut RenderState) {}
+
+    /// The window is about to close, the event loop exits after the observers ran.
+    fn on_close_requested(&mut self, _state: &mut RenderState) {}
+}
+
+/// Subscribers of the engine events published by other listeners.
+pub trait EngineObserver<S = RenderState>: Send {
+    /// The kinds of events this observer is notified of.
+    fn kinds(&self) -> &[EngineEventKind];
+
+    fn on_engine_event(&mut self, event: &EngineEvent, state: &mut S);
+}
+
+impl<O: ?Sized> ObserverList<O> {
+    fn new() -> Self {
+        ObserverList {
+            next_id: 0,
+            entries: vec![],
+        }
+    }
+
+    /// Equal priorities keep the order the observers were added in.
+    fn insert(&mut self, observer: Arc<Mutex<O>>, priority: i32) -> Subscription<O> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let index = self
+            .entries
+            .iter()
+            .position(|other| other.priority < priority)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(
+            index,
+            Entry {
+                id,
+                priority,
+                enabled: true,
+                observer,
+            },
+        );
+        Subscription {
+            id,
+            kind: PhantomData,
+        }
+    }
+
+    fn remove(&mut self, id: u64) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != len
+    }
+
+    fn set_enabled(&mut self, id: u64, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn is_enabled(&self, id: u64) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.id == id && entry.enabled)
+    }
+
+    fn enabled(&self) -> Vec<(u64, Arc<Mutex<O>>)> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| (entry.id, entry.observer.clone()))
+            .collect()
+    }
+}
+
+fn deliver_engine_events<S: EngineEventQueue>(
+    observers: &Mutex<ObserverList<dyn EngineObserver<S>>>,
+    state: &mut S,
+) {
+    for event in std::mem::take(state.engine_events()) {
+        let kind = event.kind();
+        dispatch(observers, |observer| {
+            if observer.kinds().contains(&kind) {
+                observer.on_engine_event(&event, state);
+            }
+            false
+        });
+    }
+}
+
+/// Calls `notify` on each enabled observer by priority until one handles the event.
+/// The list is only locked between the calls, so observers can change subscriptions
+/// from within `notify`.
+fn dispatch<O: ?Sized>(
+    observers: &Mutex<ObserverList<O>>,
+    mut notify: impl FnMut(&mut O) -> bool,
+) -> bool {
+    let enabled = observers.lock().unwrap().enabled();
+    for (id, observer) in enabled {
+        // An earlier observer may have removed or disabled this one
+        if !observers.lock().unwrap().is_enabled(id) {
+            continue;
+        }
+        let mut observer = observer.lock().unwrap();
+        if notify(&mut observer) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder {
+        name: &'static str,
+        handles: bool,
+    }
+
+    fn recorder(name: &'static str, handles: bool) -> Arc<Mutex<Recorder>> {
+        Arc::new(Mutex::new(Recorder { name, handles }))
+    }
+
+    fn list() -> Mutex<ObserverList<Recorder>> {
+        Mutex::new(ObserverList::new())
+    }
+
+    fn add(
+        observers: &Mutex<ObserverList<Recorder>>,
+        name: &'static str,
+        handles: bool,
+        priority: i32,
+    ) -> Subscription<Recorder> {
+        observers
+            .lock()
+            .unwrap()
+            .insert(recorder(name, handles), priority)
+    }
+
+    fn notified(observers: &Mutex<ObserverList<Recorder>>) -> (Vec<&'static str>, bool) {
+        let mut calls = Vec::new();
+        let handled = dispatch(observers, |observer| {
+            calls.push(observer.name);
+            observer.handles
+        });
+        (calls, handled)
+    }
+
+    #[test]
+    fn observers_are_called_by_priority_then_insertion_order() {
+        let observers = list();
+        add(&observers, "first", false, DEFAULT_PRIORITY);
+        add(&observers, "low", false, -5);
+        add(&observers, "high", false, 10);
+        add(&observers, "second", false, DEFAULT_PRIORITY);
+
+        assert_eq!(
+            notified(&observers),
+            (vec!["high", "first", "second", "low"], false)
+        );
+    }
+
+    #[test]
+    fn handled_events_stop_at_the_handler() {
+        let observers = list();
+        add(&observers, "camera", false, DEFAULT_PRIORITY);
+        add(&observers, "ui", true, 100);
+
+        assert_eq!(notified(&observers), (vec!["ui"], true));
+    }
+
+    #[test]
+    fn removed_observers_are_not_notified() {
+        let observers = list();
+        let first = add(&observers, "first", false, DEFAULT_PRIORITY);
+        add(&observers, "second", false, DEFAULT_PRIORITY);
+
+        assert!(observers.lock().unwrap().remove(first.id));
+        assert!(!observers.lock().unwrap().remove(first.id));
+        assert_eq!(notified(&observers), (vec!["second"], false));
+    }
+
+    #[test]
+    fn disabled_observers_are_skipped_until_enabled() {
+        let observers = list();
+        let menu = add(&observers, "menu", true, 10);
+        add(&observers, "game", false, DEFAULT_PRIORITY);
+
+        observers.lock().unwrap().set_enabled(menu.id, false);
+        assert_eq!(notified(&observers), (vec!["game"], false));
+
+        observers.lock().unwrap().set_enabled(menu.id, true);
+        assert_eq!(notified(&observers), (vec!["menu"], true));
+    }
+
+    #[test]
+    fn observers_can_unsubscribe_during_dispatch() {
+        let observers = list();
+        let once = add(&observers, "once", false, 10);
+        let later = add(&observers, "later", false, -10);
+        add(&observers, "always", false, DEFAULT_PRIORITY);
+
+        let mut calls = Vec::new();
+        dispatch(&observers, |observer| {
+            calls.push(observer.name);
+            if observer.name == "once" {
+                let mut observers = observers.lock().unwrap();
+                observers.remove(once.id);
+                observers.remove(later.id);
+            }
+            false
+        });
+
+        assert_eq!(calls, vec!["once", "always"]);
+        assert_eq!(notified(&observers), (vec!["always"], false));
+    }
+
+    #[test]
+    fn engine_events_report_their_kind() {
+        let moved = EngineEvent::CameraMoved {
+            eye: Point3::new(0.0, 0.0, 10.0),
+            target: Point3::new(0.0, 0.0, 0.0),
+        };
+        assert_eq!(moved.kind(), EngineEventKind::CameraMoved);
+    }
+
+    #[derive(Default)]
+    struct Frame {
+        engine_events: Vec<EngineEvent>,
+    }
+
+    impl EngineEventQueue for Frame {
+        fn engine_events(&mut self) -> &mut Vec<EngineEvent> {
+            &mut self.engine_events
+        }
+    }
+
+    /// Records the events it sees and publishes `follow_up` in response to each.
+    struct EventRecorder {
+        kinds: Vec<EngineEventKind>,
+        seen: Vec<EngineEvent>,
+        follow_up: Option<EngineEvent>,
+    }
+
+    fn event_recorder(
+        observers: &Mutex<ObserverList<dyn EngineObserver<Frame>>>,
+        kinds: Vec<EngineEventKind>,
+        follow_up: Option<EngineEvent>,
+    ) -> Arc<Mutex<EventRecorder>> {
+        let recorder = Arc::new(Mutex::new(EventRecorder {
+            kinds,
+            seen: Vec::new(),
+            follow_up,
+        }));
+        observers
+            .lock()
+            .unwrap()
+            .insert(recorder.clone(), DEFAULT_PRIORITY);
+        recorder
+    }
+
+    impl EngineObserver<Frame> for EventRecorder {
+        fn kinds(&self) -> &[EngineEventKind] {
+            &self.kinds
+        }
+
+        fn on_engine_event(&mut self, event: &EngineEvent, frame: &mut Frame) {
+            self.seen.push(event.clone());
+            if let Some(follow_up) = &self.follow_up {
+                frame.publish(follow_up.clone());
+            }
+        }
+    }
+
+    fn moved(z: f32) -> EngineEvent {
+        EngineEvent::CameraMoved {
+            eye: Point3::new(0.0, 0.0, z),
+            target: Point3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn spawned() -> EngineEvent {
+        EngineEvent::InstanceSpawned {
+            handle: InstanceHandle {
+                mesh: crate::render::mesh::MeshId(0),
+                index: 0,
+                generation: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn published_events_wait_for_delivery() {
+        let observers = Mutex::new(ObserverList::new());
+        let recorder = event_recorder(&observers, vec![EngineEventKind::CameraMoved], None);
+        let mut frame = Frame::default();
+
+        frame.publish(moved(1.0));
+        frame.publish(moved(2.0));
+        assert!(recorder.lock().unwrap().seen.is_empty());
+
+        deliver_engine_events(&observers, &mut frame);
+        assert_eq!(recorder.lock().unwrap().seen, vec![moved(1.0), moved(2.0)]);
+        assert!(frame.engine_events.is_empty());
+    }
+
+    #[test]
+    fn events_published_during_delivery_wait_for_the_next_frame() {
+        let observers = Mutex::new(ObserverList::new());
+        let recorder = event_recorder(
+            &observers,
+            vec![
+                EngineEventKind::CameraMoved,
+                EngineEventKind::InstanceSpawned,
+            ],
+            Some(spawned()),
+        );
+        let mut frame = Frame::default();
+
+        frame.publish(moved(1.0));
+        deliver_engine_events(&observers, &mut frame);
+        assert_eq!(recorder.lock().unwrap().seen, vec![moved(1.0)]);
+        assert_eq!(frame.engine_events, vec![spawned()]);
+
+        recorder.lock().unwrap().follow_up = None;
+        deliver_engine_events(&observers, &mut frame);
+        assert_eq!(recorder.lock().unwrap().seen, vec![moved(1.0), spawned()]);
+        assert!(frame.engine_events.is_empty());
+    }
+
+    #[test]
+    fn observers_only_see_the_kinds_they_chose() {
+        let observers = Mutex::new(ObserverList::new());
+        let camera = event_recorder(&observers, vec![EngineEventKind::CameraMoved], None);
+        let instances = event_recorder(&observers, vec![EngineEventKind::InstanceSpawned], None);
+        let mut frame = Frame::default();
+
+        frame.publish(spawned());
+        frame.publish(moved(1.0));
+        deliver_engine_events(&observers, &mut frame);
+
+        assert_eq!(camera.lock().unwrap().seen, vec![moved(1.0)]);
+        assert_eq!(instances.lock().unwrap().seen, vec![spawned()]);
+    }
 }
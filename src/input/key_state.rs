@@ -13,8 +13,13 @@ impl KeyState {
     }
 
     pub(crate) fn is_pressed(&self, key: &VirtualKeyCode) -> bool {
-        return self.state.get(key).is_some();
+        self.state.contains(key)
     }
+    /// Forgets every held key, e.g. when their release events go to another window.
+    pub(crate) fn release_all(&mut self) {
+        self.state.clear();
+    }
+
     pub(crate) fn on_key_change(&mut self, key: VirtualKeyCode, pressed: bool) {
         if pressed {
             self.state.insert(key);
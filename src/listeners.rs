@@ -1,4 +1,11 @@
 pub(crate) mod camera_keyboard_listener;
 pub(crate) mod camera_listener;
+pub(crate) mod camera_mouse_listener;
+pub(crate) mod file_drop_listener;
+pub(crate) mod focus_listener;
+pub(crate) mod help_listener;
 pub(crate) mod key_map_listener;
+pub(crate) mod pause_listener;
+pub(crate) mod scene_log_listener;
+pub(crate) mod screenshot_listener;
 pub(crate) mod test_listener;
@@ -1,16 +1,46 @@
-use winit::event::KeyboardInput;
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
 
 use crate::event::{InputObserver, UpdateObserver};
+use crate::render::camera::camera_controller::{CameraController, CameraMode};
+use crate::render::camera::fly_camera_controller::{self, FlyCameraController};
+use crate::render::camera::orbit_camera_controller::{self, OrbitCameraController};
 use crate::RenderState;
 
 pub struct CameraKeyListener {}
 
 impl InputObserver for CameraKeyListener {
-    fn on_input_change(&mut self, input: &KeyboardInput, state: &mut RenderState) {
+    /// V switches between orbiting the target and flying freely, O between perspective
+    /// and orthographic projection. Both are handled here and go no further.
+    fn on_input_change(&mut self, input: &KeyboardInput, state: &mut RenderState) -> bool {
+        if input.virtual_keycode == Some(VirtualKeyCode::O) && input.state == ElementState::Pressed
+        {
+            state.camera.toggle_projection();
+            return true;
+        }
+        if input.virtual_keycode == Some(VirtualKeyCode::V) && input.state == ElementState::Pressed
+        {
+            let grabbed = state.camera_controller.mouse_look().is_cursor_grabbed();
+            let mut controller: Box<dyn CameraController> = match state.camera_controller.mode() {
+                CameraMode::Orbit => Box::new(FlyCameraController::new(
+                    fly_camera_controller::DEFAULT_SPEED,
+                )),
+                CameraMode::Fly => Box::new(OrbitCameraController::new(
+                    orbit_camera_controller::DEFAULT_SPEED,
+                )),
+            };
+            // The window keeps its grabbed cursor, so the new controller has to know
+            if grabbed {
+                controller.mouse_look().toggle_cursor_grab();
+            }
+            state.camera_controller = controller;
+            return true;
+        }
+        // Movement keys are left to other observers as well
         state.camera_controller.process_events(input);
+        false
     }
 }
 
 impl UpdateObserver for CameraKeyListener {
-    fn on_update(&mut self, _state: &mut RenderState) {}
+    fn on_update(&mut self, _delta: f32, _state: &mut RenderState) {}
 }
@@ -1,11 +1,20 @@
-use crate::event::UpdateObserver;
+use crate::event::{EngineEvent, EngineEventQueue, UpdateObserver};
 use crate::RenderState;
 
 pub struct CameraListener {}
 
 impl UpdateObserver for CameraListener {
-    fn on_update(&mut self, state: &mut RenderState) {
-        state.camera_controller.update_camera(&mut state.camera);
-        state.camera.update();
+    fn on_update(&mut self, delta: f32, state: &mut RenderState) {
+        let (eye, target) = (state.camera.eye, state.camera.target);
+        state
+            .camera_controller
+            .update_camera(&mut state.camera, delta);
+
+        if state.camera.eye != eye || state.camera.target != target {
+            state.publish(EngineEvent::CameraMoved {
+                eye: state.camera.eye,
+                target: state.camera.target,
+            });
+        }
     }
 }
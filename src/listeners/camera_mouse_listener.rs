@@ -0,0 +1,36 @@
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta};
+
+use crate::event::InputObserver;
+use crate::RenderState;
+
+/// Feeds mouse look and scroll zoom into the camera controller.
+pub struct CameraMouseListener {}
+
+impl InputObserver for CameraMouseListener {
+    fn on_input_change(&mut self, _input: &KeyboardInput, _state: &mut RenderState) -> bool {
+        false
+    }
+
+    fn on_mouse_motion(&mut self, delta: (f64, f64), state: &mut RenderState) -> bool {
+        state
+            .camera_controller
+            .process_mouse_motion(delta.0, delta.1);
+        false
+    }
+
+    fn on_mouse_button(
+        &mut self,
+        button: MouseButton,
+        button_state: ElementState,
+        state: &mut RenderState,
+    ) -> bool {
+        state
+            .camera_controller
+            .process_mouse_button(button, button_state)
+    }
+
+    fn on_mouse_wheel(&mut self, delta: &MouseScrollDelta, state: &mut RenderState) -> bool {
+        state.camera_controller.process_scroll(delta);
+        true
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use crate::event::{EngineEvent, EngineEventQueue, WindowObserver};
+use crate::loader::gltf::load_gltf;
+use crate::RenderState;
+
+/// Adds the scene of a .gltf or .glb file dropped onto the window.
+pub struct FileDropListener {}
+
+impl WindowObserver for FileDropListener {
+    fn on_file_drop(&mut self, path: &Path, state: &mut RenderState) {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        if !matches!(extension.as_deref(), Some("gltf") | Some("glb")) {
+            log::warn!("Can't load dropped file {}", path.display());
+            return;
+        }
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match load_gltf(path) {
+            Ok(scene) => {
+                for handle in scene.instantiate(&name, state) {
+                    state.publish(EngineEvent::InstanceSpawned { handle });
+                }
+            }
+            Err(e) => log::warn!("Could not load {}: {}", path.display(), e),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use crate::event::WindowObserver;
+use crate::RenderState;
+
+/// Keys released while another window has focus never reach this one, so everything
+/// held is released when focus is lost. A grabbed cursor is let go as well.
+pub struct FocusListener {}
+
+impl WindowObserver for FocusListener {
+    fn on_focus_change(&mut self, focused: bool, state: &mut RenderState) {
+        if focused {
+            return;
+        }
+        state.key_state.release_all();
+
+        let mouse_look = state.camera_controller.mouse_look();
+        if mouse_look.is_cursor_grabbed() {
+            mouse_look.toggle_cursor_grab();
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::{Arc, Mutex};
+
+use crate::event::{EventSystem, Subscription, UpdateObserver, WeakEventSystem};
+use crate::RenderState;
+
+const CONTROLS: &str = "Controls: WASD or arrows move, Space and LShift move up and down, \
+    drag or M to look around, scroll to zoom, V switches the camera, O the projection, \
+    Pause pauses, F12 saves a screenshot, Escape quits";
+
+/// Logs the controls on the first update and unsubscribes itself.
+pub struct HelpListener {
+    event_system: WeakEventSystem,
+    subscription: Option<Subscription<dyn UpdateObserver>>,
+}
+
+impl HelpListener {
+    pub(crate) fn subscribe(event_system: &EventSystem) {
+        let listener = Arc::new(Mutex::new(HelpListener {
+            event_system: event_system.downgrade(),
+            subscription: None,
+        }));
+        let subscription = event_system.add_update_observer(listener.clone());
+        listener.lock().unwrap().subscription = Some(subscription);
+    }
+}
+
+impl UpdateObserver for HelpListener {
+    fn on_update(&mut self, _delta: f32, _state: &mut RenderState) {
+        log::info!("{}", CONTROLS);
+        if let (Some(subscription), Some(event_system)) =
+            (self.subscription.take(), self.event_system.upgrade())
+        {
+            event_system.remove(subscription);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscribed_listener_doesnt_keep_the_event_system_alive() {
+        let event_system = EventSystem::new();
+        HelpListener::subscribe(&event_system);
+        let weak = event_system.downgrade();
+
+        drop(event_system);
+        assert!(weak.upgrade().is_none());
+    }
+}
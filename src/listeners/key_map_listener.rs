@@ -1,51 +1,133 @@
-use cgmath::{Quaternion, Rotation3, Vector3};
+use cgmath::Vector3;
 use rand::Rng;
 use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
 
-use crate::event::InputObserver;
-use crate::render::instance::InstanceType;
+use crate::event::{EngineEvent, EngineEventQueue, InputObserver};
+use crate::loader::gltf::load_gltf;
+use crate::render::instance::InstanceHandle;
 use crate::{Instance, RenderState};
 
-pub struct KeyMapListener {}
+pub struct KeyMapListener {
+    spawned: Vec<InstanceHandle>,
+}
+
+impl KeyMapListener {
+    pub(crate) fn new() -> Self {
+        KeyMapListener { spawned: vec![] }
+    }
+}
+
+/// Somewhere within the area the spawn keys scatter instances over.
+fn random_position(rng: &mut impl Rng) -> Vector3<f32> {
+    Vector3::new(
+        rng.gen_range(0.0..500.0),
+        rng.gen_range(0.0..500.0),
+        rng.gen_range(0.0..500.0),
+    )
+}
 
 impl InputObserver for KeyMapListener {
-    fn on_input_change(&mut self, input: &KeyboardInput, state: &mut RenderState) {
+    fn on_input_change(&mut self, input: &KeyboardInput, state: &mut RenderState) -> bool {
         if input.virtual_keycode.is_none() {
-            return;
+            return false;
         }
         let code = input.virtual_keycode.unwrap();
         let pressed = input.state == ElementState::Pressed;
-        state.key_state.on_key_change(code, pressed);
 
         let mut rng = rand::thread_rng();
+        let spawned = self.spawned.len();
 
-        if code == VirtualKeyCode::Space {
+        if code == VirtualKeyCode::Space && pressed {
+            let cube_mesh = state.mesh_registry.find("cube").unwrap();
+            let triangle_mesh = state.mesh_registry.find("triangle").unwrap();
             for _i in 0..500 {
-                state.instance_handler.add(Instance {
-                    instance_type: InstanceType::Cube,
-                    position: Vector3 {
-                        x: (rng.gen_range(0.0..500.0)),
-                        y: (rng.gen_range(0.0..500.0)),
-                        z: (rng.gen_range(0.0..500.0)),
-                    },
-                    rotation: Quaternion::from_angle_y(cgmath::Deg(2.0)),
-                    start_offset: 0,
-                    array_index: 0,
-                    max_allowed: 500000,
-                });
-                state.instance_handler.add(Instance {
-                    instance_type: InstanceType::Triangle,
-                    position: Vector3 {
-                        x: (rng.gen_range(0.0..500.0)),
-                        y: (rng.gen_range(0.0..500.0)),
-                        z: (rng.gen_range(0.0..500.0)),
-                    },
-                    rotation: Quaternion::from_angle_y(cgmath::Deg(2.0)),
-                    start_offset: 0,
-                    array_index: 0,
-                    max_allowed: 500000,
+                let cube = state.instance_handler.add(Instance {
+                    color: [rng.gen(), rng.gen(), rng.gen()],
+                    ..Instance::new(cube_mesh, random_position(&mut rng))
                 });
+                let triangle = state
+                    .instance_handler
+                    .add(Instance::new(triangle_mesh, random_position(&mut rng)));
+                self.spawned.extend(cube);
+                self.spawned.extend(triangle);
             }
         }
+
+        if code == VirtualKeyCode::P && pressed {
+            if let Some(pyramid_mesh) = state.mesh_registry.find("pyramid") {
+                for _i in 0..500 {
+                    let pyramid = state
+                        .instance_handler
+                        .add(Instance::new(pyramid_mesh, random_position(&mut rng)));
+                    self.spawned.extend(pyramid);
+                }
+            }
+        }
+
+        if code == VirtualKeyCode::T && pressed {
+            let cube_mesh = state.mesh_registry.find("cube").unwrap();
+            let textures: Vec<_> = ["checker", "bricks"]
+                .iter()
+                .filter_map(|name| state.texture_registry.find(name))
+                .collect();
+            if !textures.is_empty() {
+                for _i in 0..500 {
+                    let cube = state.instance_handler.add(Instance {
+                        texture: textures[rng.gen_range(0..textures.len())],
+                        ..Instance::new(cube_mesh, random_position(&mut rng))
+                    });
+                    self.spawned.extend(cube);
+                }
+            }
+        }
+
+        if code == VirtualKeyCode::G && pressed {
+            let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/scenes/cubes.gltf");
+            match load_gltf(path) {
+                Ok(scene) => self.spawned.extend(scene.instantiate("cubes", state)),
+                Err(e) => log::warn!("Could not load scene {}: {}", path, e),
+            }
+        }
+
+        if code == VirtualKeyCode::Back && pressed {
+            for _i in 0..500 {
+                if let Some(handle) = self.spawned.pop() {
+                    state.instance_handler.remove(handle);
+                    state.publish(EngineEvent::InstanceRemoved { handle });
+                }
+            }
+        }
+
+        for handle in self.spawned.iter().skip(spawned) {
+            state.publish(EngineEvent::InstanceSpawned { handle: *handle });
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{headless_render_state, key};
+
+    #[test]
+    #[ignore = "needs a fallback adapter"]
+    fn releasing_space_spawns_nothing() {
+        let mut state = headless_render_state();
+        let mut listener = KeyMapListener::new();
+
+        listener.on_input_change(
+            &key(VirtualKeyCode::Space, ElementState::Released),
+            &mut state,
+        );
+        assert_eq!(state.instance_handler.total_added, 0);
+        assert!(listener.spawned.is_empty());
+
+        listener.on_input_change(
+            &key(VirtualKeyCode::Space, ElementState::Pressed),
+            &mut state,
+        );
+        assert_eq!(state.instance_handler.total_added, 1000);
     }
 }
@@ -0,0 +1,45 @@
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+use crate::event::{InputObserver, Subscription, UpdateObserver, WeakEventSystem};
+use crate::RenderState;
+
+/// Pauses the game updates while the camera keeps moving, Pause toggles it.
+pub struct PauseListener {
+    event_system: WeakEventSystem,
+    paused: Vec<Subscription<dyn UpdateObserver>>,
+    is_paused: bool,
+}
+
+impl PauseListener {
+    /// `paused` are the update observers disabled while paused.
+    pub(crate) fn new(
+        event_system: WeakEventSystem,
+        paused: Vec<Subscription<dyn UpdateObserver>>,
+    ) -> Self {
+        PauseListener {
+            event_system,
+            paused,
+            is_paused: false,
+        }
+    }
+}
+
+impl InputObserver for PauseListener {
+    fn on_input_change(&mut self, input: &KeyboardInput, _state: &mut RenderState) -> bool {
+        if input.virtual_keycode != Some(VirtualKeyCode::Pause) {
+            return false;
+        }
+        if input.state == ElementState::Pressed {
+            let event_system = match self.event_system.upgrade() {
+                Some(event_system) => event_system,
+                None => return true,
+            };
+            self.is_paused = !self.is_paused;
+            for subscription in self.paused.iter() {
+                event_system.set_enabled(*subscription, !self.is_paused);
+            }
+            log::info!("{}", if self.is_paused { "Paused" } else { "Resumed" });
+        }
+        true
+    }
+}
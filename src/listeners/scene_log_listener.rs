@@ -0,0 +1,48 @@
+use crate::event::{EngineEvent, EngineEventKind, EngineObserver};
+use crate::RenderState;
+
+/// Logs instances coming and going at debug level, camera movement is traced as it
+/// changes every step while moving.
+pub struct SceneLogListener {
+    instances: usize,
+}
+
+impl SceneLogListener {
+    pub(crate) fn new() -> Self {
+        SceneLogListener { instances: 0 }
+    }
+}
+
+impl EngineObserver for SceneLogListener {
+    fn kinds(&self) -> &[EngineEventKind] {
+        &[
+            EngineEventKind::InstanceSpawned,
+            EngineEventKind::InstanceRemoved,
+            EngineEventKind::CameraMoved,
+        ]
+    }
+
+    fn on_engine_event(&mut self, event: &EngineEvent, _state: &mut RenderState) {
+        match event {
+            EngineEvent::InstanceSpawned { handle } => {
+                self.instances += 1;
+                log::debug!(
+                    "Spawned {:?}, {} instances spawned by listeners",
+                    handle,
+                    self.instances
+                );
+            }
+            EngineEvent::InstanceRemoved { handle } => {
+                self.instances = self.instances.saturating_sub(1);
+                log::debug!(
+                    "Removed {:?}, {} instances spawned by listeners",
+                    handle,
+                    self.instances
+                );
+            }
+            EngineEvent::CameraMoved { eye, target } => {
+                log::trace!("Camera moved to {:?} looking at {:?}", eye, target);
+            }
+        }
+    }
+}
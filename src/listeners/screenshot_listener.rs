@@ -0,0 +1,36 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+use crate::event::InputObserver;
+use crate::RenderState;
+
+const SCREENSHOT_DIR: &str = "screenshots";
+
+/// Saves the current frame to `screenshots/<unix time>.png` when F12 is pressed.
+pub struct ScreenshotListener {}
+
+impl InputObserver for ScreenshotListener {
+    fn on_input_change(&mut self, input: &KeyboardInput, state: &mut RenderState) -> bool {
+        if input.virtual_keycode != Some(VirtualKeyCode::F12)
+            || input.state != ElementState::Pressed
+        {
+            return false;
+        }
+
+        if let Err(e) = std::fs::create_dir_all(SCREENSHOT_DIR) {
+            log::warn!("Could not create {}: {}", SCREENSHOT_DIR, e);
+            return true;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_millis())
+            .unwrap_or_default();
+        let path = format!("{}/{}.png", SCREENSHOT_DIR, timestamp);
+        match state.save_frame(&path) {
+            Ok(()) => log::info!("Saved screenshot to {}", path),
+            Err(e) => log::warn!("Could not save screenshot: {}", e),
+        }
+        true
+    }
+}
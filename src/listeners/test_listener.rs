@@ -1,26 +1,58 @@
+use cgmath::Vector3;
 use winit::event::{KeyboardInput, VirtualKeyCode};
 
 use crate::event::{InputObserver, UpdateObserver};
-use crate::RenderState;
+use crate::render::instance::InstanceHandle;
+use crate::{Instance, RenderState};
 
-pub struct TestListener {}
+/// Units per second the cube moves while C or X is held.
+const MOVE_SPEED: f32 = 30.0;
+
+pub struct TestListener {
+    instance: Option<InstanceHandle>,
+}
+
+impl TestListener {
+    pub(crate) fn new() -> Self {
+        TestListener { instance: None }
+    }
+}
 
 impl InputObserver for TestListener {
-    fn on_input_change(&mut self, _input: &KeyboardInput, _state: &mut RenderState) {}
+    fn on_input_change(&mut self, _input: &KeyboardInput, _state: &mut RenderState) -> bool {
+        false
+    }
 }
 
 impl UpdateObserver for TestListener {
-    fn on_update(&mut self, state: &mut RenderState) {
+    fn on_update(&mut self, delta: f32, state: &mut RenderState) {
+        if self.instance.is_none() {
+            self.instance = state.instance_handler.add(Instance {
+                color: [1.0, 0.0, 0.0],
+                ..Instance::new(
+                    state.mesh_registry.find("cube").unwrap(),
+                    Vector3::new(0.0, 0.0, 0.0),
+                )
+            });
+        }
+
+        let handle = match self.instance {
+            Some(handle) => handle,
+            None => return,
+        };
+
         if state.key_state.is_pressed(&VirtualKeyCode::C) {
-            let mut instance = state.instance_handler.get(0);
-            instance.position.x += 0.5;
-            state.instance_handler.update(0);
+            if let Some(instance) = state.instance_handler.get(handle) {
+                instance.position.x += MOVE_SPEED * delta;
+                state.instance_handler.update(handle);
+            }
         }
 
         if state.key_state.is_pressed(&VirtualKeyCode::X) {
-            let mut instance = state.instance_handler.get(0);
-            instance.position.x -= 0.5;
-            state.instance_handler.update(0);
+            if let Some(instance) = state.instance_handler.get(handle) {
+                instance.position.x -= MOVE_SPEED * delta;
+                state.instance_handler.update(handle);
+            }
         }
     }
 }
@@ -0,0 +1,459 @@
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix3, Matrix4, Point3, Quaternion, Rotation, SquareMatrix,
+    Vector3,
+};
+use image::RgbaImage;
+use std::path::Path;
+
+use crate::render::camera::camera::Projection;
+use crate::render::instance::{Instance, InstanceHandle};
+use crate::render::lib::Vertex;
+use crate::render::mesh::{generate_normals, Indices, MeshId};
+use crate::render::mesh_registry::MeshRegistry;
+use crate::render::texture_registry::{TextureId, TextureRegistry};
+use crate::RenderState;
+
+/// Axes scaled below this length are treated as collapsed.
+const MIN_SCALE: f32 = 1e-6;
+/// Largest cosine between two axes of a node that still counts as perpendicular.
+const MAX_AXIS_COSINE: f32 = 1e-3;
+
+/// One triangle primitive of a glTF mesh, colored by its material's base color factor
+/// and textured with its base color texture if it has one.
+pub struct GltfMesh {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: Indices,
+    pub base_color_texture: Option<RgbaImage>,
+}
+
+/// A node of the scene with its transform resolved through the node hierarchy.
+pub struct GltfNode {
+    pub mesh: usize,
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+pub struct GltfCamera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub nodes: Vec<GltfNode>,
+    pub camera: Option<GltfCamera>,
+}
+
+impl GltfScene {
+    /// Registers every mesh and base color texture, adds an instance per node and
+    /// switches to the scene's camera if it has one. The camera keeps the window's
+    /// aspect ratio, the one stored in the file would stretch the view.
+    pub fn instantiate(self, name: &str, state: &mut RenderState) -> Vec<InstanceHandle> {
+        let mesh_ids: Vec<_> = self
+            .meshes
+            .into_iter()
+            .map(|mesh| {
+                register_mesh(
+                    name,
+                    mesh,
+                    &mut state.mesh_registry,
+                    &mut state.texture_registry,
+                )
+            })
+            .collect();
+
+        let handles = self
+            .nodes
+            .iter()
+            .filter_map(|node| {
+                let (mesh, texture) = mesh_ids[node.mesh];
+                state.instance_handler.add(Instance {
+                    rotation: node.rotation,
+                    scale: node.scale,
+                    texture,
+                    ..Instance::new(mesh, node.position)
+                })
+            })
+            .collect();
+
+        if let Some(camera) = self.camera {
+            state.camera.eye = camera.eye;
+            state.camera.target = camera.target;
+            state.camera.up = camera.up;
+            state.camera.projection = Projection::Perspective { fovy: camera.fovy };
+            state.camera.znear = camera.znear;
+            state.camera.zfar = camera.zfar;
+            state.camera.update();
+        }
+
+        handles
+    }
+}
+
+/// Registers `mesh` and its base color texture under the scene's name, or reuses them
+/// when the same scene was instantiated before, so loading it again doesn't use up
+/// texture layers or duplicate the geometry.
+fn register_mesh(
+    scene: &str,
+    mesh: GltfMesh,
+    mesh_registry: &mut MeshRegistry,
+    texture_registry: &mut TextureRegistry,
+) -> (MeshId, TextureId) {
+    let mesh_name = format!("{}/{}", scene, mesh.name);
+    let texture = match &mesh.base_color_texture {
+        Some(image) => texture_registry
+            .find(&mesh_name)
+            .unwrap_or_else(|| texture_registry.register(&mesh_name, image)),
+        None => TextureId::NONE,
+    };
+    let mesh = mesh_registry
+        .find(&mesh_name)
+        .unwrap_or_else(|| mesh_registry.register(&mesh_name, &mesh.vertices, mesh.indices));
+    (mesh, texture)
+}
+
+/// Loads the default scene of a .gltf or .glb file.
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<GltfScene, gltf::Error> {
+    let (document, buffers, images) = gltf::import(path)?;
+
+    // Every primitive becomes its own mesh, remember where each glTF mesh's primitives went
+    let mut meshes = Vec::new();
+    let mut primitive_meshes = Vec::new();
+    for mesh in document.meshes() {
+        let mut primitives = Vec::new();
+        for (i, primitive) in mesh.primitives().enumerate() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::warn!("Skipping non triangle primitive of mesh {}", mesh.index());
+                continue;
+            }
+            let name = format!("{}.{}", mesh.name().unwrap_or("mesh"), i);
+            if let Some(mesh) = load_primitive(name, &primitive, &buffers, &images) {
+                primitives.push(meshes.len());
+                meshes.push(mesh);
+            }
+        }
+        primitive_meshes.push(primitives);
+    }
+
+    let mut nodes = Vec::new();
+    let mut camera = None;
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next());
+    if let Some(scene) = scene {
+        for node in scene.nodes() {
+            visit_node(
+                &node,
+                Matrix4::identity(),
+                &primitive_meshes,
+                &mut nodes,
+                &mut camera,
+            );
+        }
+    }
+
+    Ok(GltfScene {
+        meshes,
+        nodes,
+        camera,
+    })
+}
+
+fn load_primitive(
+    name: String,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    images: &[gltf::image::Data],
+) -> Option<GltfMesh> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let positions: Vec<[f32; 3]> = reader.read_positions()?.collect();
+
+    let pbr = primitive.material().pbr_metallic_roughness();
+    let factor = pbr.base_color_factor();
+    let colors: Vec<[f32; 3]> = match reader.read_colors(0) {
+        Some(colors) => colors.into_rgb_f32().collect(),
+        None => vec![[1.0, 1.0, 1.0]; positions.len()],
+    };
+
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|normals| normals.collect());
+    let tex_coords: Option<Vec<[f32; 2]>> = reader
+        .read_tex_coords(0)
+        .map(|tex_coords| tex_coords.into_f32().collect());
+
+    let mut vertices = positions
+        .iter()
+        .zip(colors.iter())
+        .enumerate()
+        .map(|(i, (position, color))| Vertex {
+            position: *position,
+            color: [
+                color[0] * factor[0],
+                color[1] * factor[1],
+                color[2] * factor[2],
+            ],
+            normal: normals
+                .as_ref()
+                .map_or([0.0, 0.0, 0.0], |normals| normals[i]),
+            tex_coords: tex_coords
+                .as_ref()
+                .map_or([0.0, 0.0], |tex_coords| tex_coords[i]),
+        })
+        .collect::<Vec<_>>();
+
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    if normals.is_none() {
+        generate_normals(&mut vertices, &indices);
+    }
+
+    let base_color_texture = pbr
+        .base_color_texture()
+        .and_then(|info| to_rgba(&images[info.texture().source().index()]));
+
+    Some(GltfMesh {
+        name,
+        indices: Indices::narrow(vertices.len(), indices),
+        vertices,
+        base_color_texture,
+    })
+}
+
+fn to_rgba(image: &gltf::image::Data) -> Option<RgbaImage> {
+    let pixels = match image.format {
+        gltf::image::Format::R8G8B8A8 => image.pixels.clone(),
+        gltf::image::Format::R8G8B8 => image
+            .pixels
+            .chunks(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        format => {
+            log::warn!("Unsupported base color texture format {:?}", format);
+            return None;
+        }
+    };
+    RgbaImage::from_raw(image.width, image.height, pixels)
+}
+
+fn visit_node(
+    node: &gltf::Node,
+    parent: Matrix4<f32>,
+    primitive_meshes: &[Vec<usize>],
+    nodes: &mut Vec<GltfNode>,
+    camera: &mut Option<GltfCamera>,
+) {
+    let world = parent * Matrix4::from(node.transform().matrix());
+    // A collapsed axis collapses every child as well, so the whole subtree is skipped
+    let (position, rotation, scale) = match decompose(&world) {
+        Some(parts) => parts,
+        None => {
+            log::warn!("Skipping node {} scaled to zero", node.index());
+            return;
+        }
+    };
+    if is_sheared(&world) {
+        log::warn!(
+            "Node {} is sheared, its instances can only approximate the transform",
+            node.index()
+        );
+    }
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in primitive_meshes[mesh.index()].iter() {
+            nodes.push(GltfNode {
+                mesh: *primitive,
+                position,
+                rotation,
+                scale,
+            });
+        }
+    }
+
+    // The first camera found is used, glTF cameras look down their local -Z axis
+    if let (Some(gltf_camera), None) = (node.camera(), camera.as_ref()) {
+        match gltf_camera.projection() {
+            gltf::camera::Projection::Perspective(perspective) => {
+                let eye = Point3::from_vec(position);
+                *camera = Some(GltfCamera {
+                    eye,
+                    target: eye + rotation.rotate_vector(-Vector3::unit_z()),
+                    up: rotation.rotate_vector(Vector3::unit_y()),
+                    fovy: perspective.yfov().to_degrees(),
+                    znear: perspective.znear(),
+                    zfar: perspective.zfar().unwrap_or(500.0),
+                });
+            }
+            gltf::camera::Projection::Orthographic(_) => {
+                log::warn!("Skipping orthographic camera {}", gltf_camera.index());
+            }
+        }
+    }
+
+    for child in node.children() {
+        visit_node(&child, world, primitive_meshes, nodes, camera);
+    }
+}
+
+/// Splits `matrix` into translation, rotation and scale. A mirroring matrix gets a
+/// negative X scale so the rest is a proper rotation, and `None` is returned when an
+/// axis is scaled to zero as no rotation can be recovered then. The axes of a sheared
+/// matrix are made perpendicular again, keeping the direction of X.
+fn decompose(matrix: &Matrix4<f32>) -> Option<(Vector3<f32>, Quaternion<f32>, Vector3<f32>)> {
+    let position = matrix.w.truncate();
+    let axes = linear_part(matrix);
+    let mut scale = Vector3::new(axes.x.magnitude(), axes.y.magnitude(), axes.z.magnitude());
+    if scale.x < MIN_SCALE || scale.y < MIN_SCALE || scale.z < MIN_SCALE {
+        return None;
+    }
+    if axes.determinant() < 0.0 {
+        scale.x = -scale.x;
+    }
+    let x = axes.x / scale.x;
+    let y = (axes.y - x * x.dot(axes.y)).normalize();
+    let rotation = Matrix3::from_cols(x, y, x.cross(y));
+    Some((position, Quaternion::from(rotation).normalize(), scale))
+}
+
+/// Whether the axes of `matrix` aren't perpendicular, which happens when a rotated
+/// node has a non-uniformly scaled parent. Instances have no way to express that.
+fn is_sheared(matrix: &Matrix4<f32>) -> bool {
+    let axes = linear_part(matrix);
+    let (x, y, z) = (axes.x.normalize(), axes.y.normalize(), axes.z.normalize());
+    x.dot(y).abs() > MAX_AXIS_COSINE
+        || x.dot(z).abs() > MAX_AXIS_COSINE
+        || y.dot(z).abs() > MAX_AXIS_COSINE
+}
+
+fn linear_part(matrix: &Matrix4<f32>) -> Matrix3<f32> {
+    Matrix3::from_cols(
+        matrix.x.truncate(),
+        matrix.y.truncate(),
+        matrix.z.truncate(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::assert_relative_eq;
+
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!(
+            "{}/tests/fixtures/gltf/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        )
+    }
+
+    #[test]
+    fn resolves_node_hierarchy_transforms() {
+        let scene = load_gltf(fixture("nodes.gltf")).unwrap();
+
+        assert_eq!(scene.meshes.len(), 1);
+        assert_eq!(scene.nodes.len(), 1);
+
+        // The child sits 1 unit along its parent's X axis, which is rotated onto -Z
+        let node = &scene.nodes[0];
+        assert_eq!(node.mesh, 0);
+        assert_relative_eq!(node.position, Vector3::new(0.0, 0.0, -6.0), epsilon = 1e-5);
+        assert_relative_eq!(node.scale, Vector3::new(2.0, 2.0, 2.0), epsilon = 1e-5);
+        assert_relative_eq!(
+            node.rotation.rotate_vector(Vector3::unit_x()),
+            -Vector3::unit_z(),
+            epsilon = 1e-5
+        );
+    }
+
+    #[test]
+    fn reads_geometry_and_base_color_factor() {
+        let scene = load_gltf(fixture("nodes.gltf")).unwrap();
+        let mesh = &scene.meshes[0];
+
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.indices.to_u32(), vec![0, 1, 2]);
+        assert_eq!(mesh.vertices[1].normal, [0.0, 0.0, 1.0]);
+        assert!(mesh.vertices.iter().all(|v| v.color == [0.0, 1.0, 0.0]));
+        assert!(mesh.base_color_texture.is_none());
+    }
+
+    #[test]
+    fn maps_perspective_camera() {
+        let scene = load_gltf(fixture("nodes.gltf")).unwrap();
+        let camera = scene.camera.unwrap();
+
+        assert_relative_eq!(camera.eye.to_vec(), Vector3::new(0.0, 1.0, 3.0));
+        assert_relative_eq!(camera.target.to_vec(), Vector3::new(0.0, 1.0, 2.0));
+        assert_relative_eq!(camera.up, Vector3::unit_y());
+        assert_relative_eq!(camera.fovy, 1.0f32.to_degrees());
+        assert_eq!(camera.znear, 0.1);
+        assert_eq!(camera.zfar, 100.0);
+    }
+
+    #[test]
+    fn instantiating_a_scene_again_reuses_its_meshes_and_textures() {
+        let mut mesh_registry = MeshRegistry::new();
+        let mut texture_registry = TextureRegistry::new();
+        let mut register = |scene: &str| {
+            let mut mesh = load_gltf(fixture("nodes.gltf")).unwrap().meshes.remove(0);
+            mesh.base_color_texture = Some(RgbaImage::new(4, 4));
+            register_mesh(scene, mesh, &mut mesh_registry, &mut texture_registry)
+        };
+
+        let first = register("nodes");
+        assert_eq!(register("nodes"), first);
+        assert_ne!(register("other").0, first.0);
+        assert_eq!(mesh_registry.meshes.len(), 2);
+        assert_eq!(texture_registry.layers().len(), 3);
+    }
+
+    #[test]
+    fn zero_scale_is_not_decomposed() {
+        let matrix = Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0))
+            * Matrix4::from_nonuniform_scale(1.0, 0.0, 1.0);
+        assert!(decompose(&matrix).is_none());
+    }
+
+    #[test]
+    fn mirrored_matrix_decomposes_into_a_rotation_and_negative_scale() {
+        let matrix = Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0))
+            * Matrix4::from_angle_y(cgmath::Deg(90.0))
+            * Matrix4::from_nonuniform_scale(2.0, 3.0, -4.0);
+        let (position, rotation, scale) = decompose(&matrix).unwrap();
+
+        assert!(scale.x < 0.0);
+        assert_relative_eq!(Matrix3::from(rotation).determinant(), 1.0, epsilon = 1e-5);
+        let recomposed = Matrix4::from_translation(position)
+            * Matrix4::from(rotation)
+            * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z);
+        assert_relative_eq!(recomposed, matrix, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn rotated_child_of_a_non_uniformly_scaled_parent_is_sheared() {
+        let parent = Matrix4::from_nonuniform_scale(2.0, 1.0, 1.0);
+        let child = Matrix4::from_angle_z(cgmath::Deg(45.0));
+        assert!(is_sheared(&(parent * child)));
+        assert!(!is_sheared(&(Matrix4::from_scale(2.0) * child)));
+        assert!(!is_sheared(
+            &(parent * Matrix4::from_angle_z(cgmath::Deg(90.0)))
+        ));
+
+        // The rotation stays a proper one and keeps the direction of the child's X axis
+        let (_, rotation, _) = decompose(&(parent * child)).unwrap();
+        let rotation = Matrix3::from(rotation);
+        assert_relative_eq!(rotation.determinant(), 1.0, epsilon = 1e-5);
+        assert_relative_eq!(
+            rotation.x,
+            Vector3::new(2.0, 1.0, 0.0).normalize(),
+            epsilon = 1e-5
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt::Debug;
+use std::path::Path;
+
+use crate::render::lib::Vertex;
+use crate::render::mesh::{generate_normals, Indices, MeshId};
+use crate::render::mesh_registry::MeshRegistry;
+
+/// Color given to faces without a material.
+pub const DEFAULT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// Geometry of every object in an OBJ file merged into a single mesh.
+pub struct ObjMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Indices,
+}
+
+impl ObjMesh {
+    pub fn register(self, mesh_registry: &mut MeshRegistry, name: &str) -> MeshId {
+        mesh_registry.register(name, &self.vertices, self.indices)
+    }
+}
+
+/// Loads an OBJ file and its MTL materials, triangulating polygons. Vertices are
+/// colored with the diffuse color of their material, objects without normals get
+/// generated ones.
+pub fn load_obj<P: AsRef<Path> + Debug>(path: P) -> Result<ObjMesh, tobj::LoadError> {
+    let (models, materials) = tobj::load_obj(
+        path.as_ref(),
+        &tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ignore_points: true,
+            ignore_lines: true,
+        },
+    )?;
+
+    let materials = materials.unwrap_or_else(|e| {
+        log::warn!("Could not load materials for {:?}: {}", path, e);
+        Vec::new()
+    });
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    for model in models {
+        let mesh = model.mesh;
+        let color = mesh
+            .material_id
+            .and_then(|id| materials.get(id))
+            .map(|material| material.diffuse)
+            .unwrap_or(DEFAULT_COLOR);
+
+        let offset = vertices.len();
+        for i in 0..mesh.positions.len() / 3 {
+            vertices.push(Vertex {
+                position: [
+                    mesh.positions[i * 3],
+                    mesh.positions[i * 3 + 1],
+                    mesh.positions[i * 3 + 2],
+                ],
+                color,
+                normal: match mesh.normals.get(i * 3..i * 3 + 3) {
+                    Some(n) => [n[0], n[1], n[2]],
+                    None => [0.0, 0.0, 0.0],
+                },
+                // OBJ puts the V origin at the bottom of the image, wgpu at the top
+                tex_coords: match mesh.texcoords.get(i * 2..i * 2 + 2) {
+                    Some(t) => [t[0], 1.0 - t[1]],
+                    None => [0.0, 0.0],
+                },
+            });
+        }
+        if mesh.normals.is_empty() {
+            generate_normals(&mut vertices[offset..], &mesh.indices);
+        }
+        indices.extend(mesh.indices.iter().map(|i| i + offset as u32));
+    }
+
+    Ok(ObjMesh {
+        indices: Indices::narrow(vertices.len(), indices),
+        vertices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/obj/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn indices(mesh: &ObjMesh) -> Vec<u32> {
+        mesh.indices.to_u32()
+    }
+
+    #[test]
+    fn loads_positions_normals_tex_coords_and_material_color() {
+        let mesh = load_obj(fixture("quad.obj")).unwrap();
+
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(indices(&mesh), vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.vertices[2].position, [1.0, 1.0, 0.0]);
+        assert_eq!(mesh.vertices[2].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(mesh.vertices[2].tex_coords, [1.0, 0.0]);
+        assert!(mesh.vertices.iter().all(|v| v.color == [1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn triangulates_polygons_and_merges_objects() {
+        let mesh = load_obj(fixture("shapes.obj")).unwrap();
+
+        // A plain triangle followed by a pentagon that becomes a fan of three triangles
+        assert_eq!(mesh.vertices.len(), 8);
+        assert_eq!(indices(&mesh), vec![0, 1, 2, 3, 4, 5, 3, 5, 6, 3, 6, 7]);
+        assert_eq!(mesh.vertices[0].color, DEFAULT_COLOR);
+        // Neither object has normals, both face +Z
+        assert!(mesh.vertices.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+        assert_eq!(mesh.vertices[3].color, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        assert!(load_obj(fixture("missing.obj")).is_err());
+    }
+
+    #[test]
+    fn uses_32_bit_indices_only_when_needed() {
+        assert!(matches!(
+            Indices::narrow(u16::MAX as usize + 1, vec![u16::MAX as u32]),
+            Indices::U16(_)
+        ));
+        assert!(matches!(
+            Indices::narrow(u16::MAX as usize + 2, vec![u16::MAX as u32 + 1]),
+            Indices::U32(_)
+        ));
+    }
+}
@@ -10,79 +10,114 @@ use winit::{
 use render::instance::Instance;
 
 use crate::event::{EventMatcher, EventSystem};
+use crate::render::lib::{Culling, RenderSettings};
 use crate::render::render_state::RenderState;
-use crate::render::render_state_factory::create_render_state;
+use crate::render::render_state_factory::{create_headless_render_state, create_render_state};
 
 mod data;
 mod event;
 mod input;
 mod listeners;
+mod loader;
 mod render;
 mod rotation;
+#[cfg(test)]
+mod test_util;
+mod time;
+
+/// Frames drawn by `--headless` before exiting.
+const HEADLESS_FRAMES: usize = 60;
+/// Seconds each `--headless` frame advances, so runs are reproducible.
+const HEADLESS_DELTA: f32 = 1.0 / 60.0;
 
 fn main() {
     env_logger::init();
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
-    window.set_title("Vulkan Engine");
-    window.set_inner_size(Size::Physical(PhysicalSize::new(1920, 1080)));
 
-    let mut event_system = EventSystem::new();
+    let event_system = EventSystem::new();
 
-    let test_listener = Arc::new(Mutex::new(listeners::test_listener::TestListener {}));
+    let test_listener = Arc::new(Mutex::new(listeners::test_listener::TestListener::new()));
     let camera_key_listener = Arc::new(Mutex::new(
         listeners::camera_keyboard_listener::CameraKeyListener {},
     ));
-    let key_map_listener = Arc::new(Mutex::new(listeners::key_map_listener::KeyMapListener {}));
+    let key_map_listener = Arc::new(Mutex::new(
+        listeners::key_map_listener::KeyMapListener::new(),
+    ));
     let camera_listener = Arc::new(Mutex::new(listeners::camera_listener::CameraListener {}));
+    let camera_mouse_listener = Arc::new(Mutex::new(
+        listeners::camera_mouse_listener::CameraMouseListener {},
+    ));
+    let screenshot_listener = Arc::new(Mutex::new(
+        listeners::screenshot_listener::ScreenshotListener {},
+    ));
+    let focus_listener = Arc::new(Mutex::new(listeners::focus_listener::FocusListener {}));
+    let file_drop_listener = Arc::new(Mutex::new(
+        listeners::file_drop_listener::FileDropListener {},
+    ));
 
-    event_system.add_update_observer(test_listener);
+    let test_update = event_system.add_update_observer(test_listener);
     event_system.add_input_observer(camera_key_listener);
     event_system.add_input_observer(key_map_listener);
     event_system.add_update_observer(camera_listener);
+    // Screenshots are taken before anything else sees F12
+    event_system.add_input_observer_with_priority(screenshot_listener, 10);
+    event_system.add_input_observer(camera_mouse_listener);
+    event_system.add_window_observer(focus_listener);
+    event_system.add_window_observer(file_drop_listener);
+
+    let pause_listener = Arc::new(Mutex::new(listeners::pause_listener::PauseListener::new(
+        event_system.downgrade(),
+        vec![test_update],
+    )));
+    event_system.add_input_observer(pause_listener);
+    listeners::help_listener::HelpListener::subscribe(&event_system);
+    let scene_log_listener = Arc::new(Mutex::new(
+        listeners::scene_log_listener::SceneLogListener::new(),
+    ));
+    event_system.add_engine_observer(scene_log_listener);
+
+    if std::env::args().any(|arg| arg == "--headless") {
+        run_headless(&event_system);
+        return;
+    }
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    window.set_title("Vulkan Engine");
+    window.set_inner_size(Size::Physical(PhysicalSize::new(1920, 1080)));
 
     // State::new uses async code, so we're going to wait for it to finish
-    let mut state: RenderState = pollster::block_on(create_render_state(&window));
+    let mut state: RenderState =
+        pollster::block_on(create_render_state(&window, settings_from_args()));
 
     event_loop.run(move |event, _, control_flow| {
         let id = window.id();
 
-        EventMatcher::on_event(&id, &event, &event_system, &mut state);
+        let handled = EventMatcher::on_event(&id, &event, &event_system, &mut state);
+
+        if let Some(grab) = state.camera_controller.take_cursor_grab_change() {
+            if let Err(e) = window.set_cursor_grab(grab) {
+                log::warn!("Could not grab the cursor: {}", e);
+            }
+            window.set_cursor_visible(!grab);
+        }
 
         match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == id => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
+            } if window_id == id && !handled && !state.input(event) => match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
                             ..
-                        } => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &&mut so w have to dereference it twice
-                            state.resize(**new_inner_size);
-                        }
-                        _ => {}
-                    }
-                }
-                match event {
-                    WindowEvent::KeyboardInput { input, .. } => {
-                        event_system.notify_keyboard_input(input, &mut state);
-                    }
-                    _ => {}
-                }
-            }
+                        },
+                    ..
+                } => *control_flow = ControlFlow::Exit,
+                _ => {}
+            },
             Event::RedrawRequested(_) => {
                 state.update();
                 match state.render() {
@@ -104,3 +139,45 @@ fn main() {
         }
     });
 }
+
+/// `--no-culling` draws every instance and `--cpu-culling` culls without the compute
+/// pass, e.g. to compare frame times.
+fn settings_from_args() -> RenderSettings {
+    let mut settings = RenderSettings::default();
+    for arg in std::env::args() {
+        match arg.as_str() {
+            "--no-culling" => settings.culling = Culling::Disabled,
+            "--cpu-culling" => settings.culling = Culling::Cpu,
+            _ => {}
+        }
+    }
+    settings
+}
+
+/// Runs the same update and render flow as the window loop against an offscreen
+/// target, `--fallback` forces a software adapter.
+fn run_headless(event_system: &EventSystem) {
+    let settings = RenderSettings {
+        force_fallback_adapter: std::env::args().any(|arg| arg == "--fallback"),
+        ..settings_from_args()
+    };
+    let mut state = match pollster::block_on(create_headless_render_state(
+        PhysicalSize::new(1920, 1080),
+        settings,
+    )) {
+        Some(state) => state,
+        None => {
+            eprintln!("No adapter available for headless rendering");
+            return;
+        }
+    };
+
+    for _ in 0..HEADLESS_FRAMES {
+        event_system.run_simulation(HEADLESS_DELTA, &mut state);
+        event_system.notify_engine_events(&mut state);
+        state.update();
+        if let Err(e) = state.render() {
+            eprintln!("{:?}", e);
+        }
+    }
+}
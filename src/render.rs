@@ -1,8 +1,19 @@
 pub mod camera;
+pub mod capture;
+pub mod culling;
+#[cfg(test)]
+mod golden;
+pub mod gpu_culling;
 pub mod instance;
 pub mod instance_handler;
+pub mod instance_region;
 pub mod lib;
+pub mod light;
+pub mod mesh;
+pub mod mesh_registry;
 pub mod render_state;
 pub mod render_state_factory;
 pub mod renderer;
+pub mod texture;
+pub mod texture_registry;
 pub mod updater;
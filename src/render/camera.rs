@@ -1,2 +1,5 @@
+#[allow(clippy::module_inception)]
 pub mod camera;
 pub mod camera_controller;
+pub mod fly_camera_controller;
+pub mod orbit_camera_controller;
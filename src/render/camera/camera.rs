@@ -1,10 +1,20 @@
 use crate::render::lib::OPENGL_TO_WGPU_MATRIX;
-use cgmath::{Deg, Point3, SquareMatrix, Vector3};
+use cgmath::{Angle, Deg, InnerSpace, Point3, Rad, SquareMatrix, Vector3};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
-    view_proj: [[f32; 4]; 4],
+    view_position: [f32; 4],
+    pub(crate) view_proj: [[f32; 4]; 4],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    /// `fovy` is the vertical field of view in degrees.
+    Perspective { fovy: f32 },
+    /// `height` is how many world units fit between the bottom and top of the view,
+    /// smaller values zoom in.
+    Orthographic { height: f32 },
 }
 
 pub struct Camera {
@@ -12,22 +22,81 @@ pub struct Camera {
     pub(crate) target: cgmath::Point3<f32>,
     pub(crate) up: cgmath::Vector3<f32>,
     pub(crate) aspect: f32,
-    pub(crate) fovy: f32,
+    pub(crate) projection: Projection,
     pub(crate) znear: f32,
     pub(crate) zfar: f32,
     pub(crate) model_rotation: cgmath::Deg<f32>,
     pub(crate) uniform: CameraUniform,
+    /// Eye and target before the last simulation step, the uniform is blended from
+    /// these towards the current ones.
+    pub(crate) previous: Option<(Point3<f32>, Point3<f32>)>,
 }
 
 impl Camera {
-    fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+    fn build_projection_matrix(
+        &self,
+        eye: Point3<f32>,
+        target: Point3<f32>,
+    ) -> cgmath::Matrix4<f32> {
+        let view = cgmath::Matrix4::look_at_rh(eye, target, self.up);
+        let proj = match self.projection {
+            Projection::Perspective { fovy } => {
+                cgmath::perspective(cgmath::Deg(fovy), self.aspect, self.znear, self.zfar)
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * self.aspect;
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.znear,
+                    self.zfar,
+                )
+            }
+        };
         OPENGL_TO_WGPU_MATRIX * proj * view * cgmath::Matrix4::from_angle_z(self.model_rotation)
     }
 
+    /// Switches between perspective and orthographic projection. The new projection
+    /// shows the same height at the target's distance, so the view doesn't jump.
+    pub fn toggle_projection(&mut self) {
+        let distance = (self.target - self.eye).magnitude();
+        self.projection = match self.projection {
+            Projection::Perspective { fovy } => Projection::Orthographic {
+                height: 2.0 * distance * (Deg(fovy) / 2.0).tan(),
+            },
+            Projection::Orthographic { height } => Projection::Perspective {
+                fovy: Deg::from(Rad((height / (2.0 * distance)).atan() * 2.0)).0,
+            },
+        };
+    }
+
+    /// Moves the uniform straight to the current eye and target, e.g. after the camera
+    /// was placed somewhere new.
     pub fn update(&mut self) {
-        self.uniform.view_proj = (self.build_projection_matrix()
+        self.previous = None;
+        self.update_interpolated(1.0);
+    }
+
+    /// Remembers the eye and target before a simulation step moves them.
+    pub fn begin_step(&mut self) {
+        self.previous = Some((self.eye, self.target));
+    }
+
+    /// Builds the uniform from the pose `alpha` of the way from the one before the last
+    /// step to the current one.
+    pub fn update_interpolated(&mut self, alpha: f32) {
+        let (eye, target) = match self.previous {
+            Some((eye, target)) => (
+                eye + (self.eye - eye) * alpha,
+                target + (self.target - target) * alpha,
+            ),
+            None => (self.eye, self.target),
+        };
+        self.uniform.view_position = eye.to_homogeneous().into();
+        self.uniform.view_proj = (self.build_projection_matrix(eye, target)
             * cgmath::Matrix4::from_angle_z(self.model_rotation))
         .into()
     }
@@ -52,13 +121,90 @@ impl Default for Camera {
                 z: (0.0),
             },
             aspect: 0.0,
-            fovy: 0.0,
+            projection: Projection::Perspective { fovy: 0.0 },
             znear: 0.0,
             zfar: 0.0,
             model_rotation: Deg(0.0),
             uniform: CameraUniform {
+                view_position: [0.0, 0.0, 0.0, 1.0],
                 view_proj: cgmath::Matrix4::identity().into(),
             },
+            previous: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera {
+            eye: Point3::new(0.0, 0.0, 10.0),
+            up: Vector3::unit_y(),
+            aspect: 1.0,
+            projection: Projection::Perspective { fovy: 45.0 },
+            znear: 0.1,
+            zfar: 100.0,
+            ..Camera::default()
+        }
+    }
+
+    #[test]
+    fn uniform_blends_between_steps() {
+        let mut camera = camera();
+        camera.begin_step();
+        camera.eye.z = 20.0;
+
+        camera.update_interpolated(0.25);
+        assert_eq!(camera.uniform.view_position, [0.0, 0.0, 12.5, 1.0]);
+        camera.update_interpolated(1.0);
+        assert_eq!(camera.uniform.view_position, [0.0, 0.0, 20.0, 1.0]);
+    }
+
+    #[test]
+    fn update_snaps_to_the_current_pose() {
+        let mut camera = camera();
+        camera.begin_step();
+        camera.eye.z = 20.0;
+
+        camera.update();
+        camera.update_interpolated(0.0);
+        assert_eq!(camera.uniform.view_position, [0.0, 0.0, 20.0, 1.0]);
+    }
+
+    #[test]
+    fn toggling_keeps_the_height_at_the_target() {
+        let mut camera = camera();
+
+        camera.toggle_projection();
+        let height = match camera.projection {
+            Projection::Orthographic { height } => height,
+            projection => panic!("Expected orthographic, got {:?}", projection),
+        };
+        assert!((height - 20.0 * (22.5f32).to_radians().tan()).abs() < 1e-4);
+
+        camera.toggle_projection();
+        match camera.projection {
+            Projection::Perspective { fovy } => assert!((fovy - 45.0).abs() < 1e-3),
+            projection => panic!("Expected perspective, got {:?}", projection),
+        }
+    }
+
+    #[test]
+    fn orthographic_size_does_not_depend_on_distance() {
+        let mut camera = Camera {
+            projection: Projection::Orthographic { height: 4.0 },
+            ..camera()
+        };
+        let corner = cgmath::Vector4::new(2.0 * camera.aspect, 2.0, 0.0, 1.0);
+
+        for z in [5.0, 50.0] {
+            camera.eye.z = z;
+            camera.update();
+            let clip = cgmath::Matrix4::from(camera.uniform.view_proj) * corner;
+            assert!((clip.x / clip.w - 1.0).abs() < 1e-5);
+            assert!((clip.y / clip.w - 1.0).abs() < 1e-5);
         }
     }
 }
@@ -1,95 +1,143 @@
 use crate::render::camera::camera::Camera;
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
-
-pub struct CameraController {
-    speed: f32,
-    is_up_pressed: bool,
-    is_down_pressed: bool,
-    is_forward_pressed: bool,
-    is_backward_pressed: bool,
-    is_left_pressed: bool,
-    is_right_pressed: bool,
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta};
+
+/// Radians the camera turns per pixel of mouse motion.
+pub const MOUSE_SENSITIVITY: f32 = 0.005;
+/// Touchpads scroll in pixels, this many make up one line.
+const PIXELS_PER_LINE: f32 = 20.0;
+/// Keeps the view from passing over the poles, where the up vector would flip.
+pub const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    Orbit,
+    Fly,
 }
 
-impl CameraController {
-    pub fn new(speed: f32) -> Self {
-        Self {
-            speed,
-            is_up_pressed: false,
-            is_down_pressed: false,
-            is_forward_pressed: false,
-            is_backward_pressed: false,
-            is_left_pressed: false,
-            is_right_pressed: false,
-        }
+/// Turns input into camera movement. Input is gathered as it arrives and applied to
+/// the camera once per update.
+pub trait CameraController {
+    fn mode(&self) -> CameraMode;
+
+    fn mouse_look(&mut self) -> &mut MouseLook;
+
+    fn process_events(&mut self, event: &KeyboardInput) -> bool;
+
+    fn process_scroll(&mut self, delta: &MouseScrollDelta);
+
+    /// Applies the input gathered since the last update, `delta` is in seconds.
+    fn update_camera(&mut self, camera: &mut Camera, delta: f32);
+
+    fn process_mouse_button(&mut self, button: MouseButton, state: ElementState) -> bool {
+        self.mouse_look().process_mouse_button(button, state)
     }
 
-    pub fn process_events(&mut self, event: &KeyboardInput) -> bool {
-        if event.virtual_keycode.is_none() {
-            return false;
-        }
+    fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        self.mouse_look().process_mouse_motion(dx, dy);
+    }
 
-        let is_pressed = event.state == ElementState::Pressed;
-        let keycode = event.virtual_keycode.unwrap();
+    /// Returns whether the cursor should be grabbed if that changed since the last call,
+    /// the window is only reachable from the event loop.
+    fn take_cursor_grab_change(&mut self) -> Option<bool> {
+        self.mouse_look().take_cursor_grab_change()
+    }
+}
 
-        match keycode {
-            VirtualKeyCode::Space => {
-                self.is_up_pressed = is_pressed;
-                true
-            }
-            VirtualKeyCode::LShift => {
-                self.is_down_pressed = is_pressed;
-                true
-            }
-            VirtualKeyCode::W | VirtualKeyCode::Up => {
-                self.is_forward_pressed = is_pressed;
-                true
-            }
-            VirtualKeyCode::A | VirtualKeyCode::Left => {
-                self.is_left_pressed = is_pressed;
-                true
-            }
-            VirtualKeyCode::S | VirtualKeyCode::Down => {
-                self.is_backward_pressed = is_pressed;
-                true
-            }
-            VirtualKeyCode::D | VirtualKeyCode::Right => {
-                self.is_right_pressed = is_pressed;
+/// Mouse motion shared by the controllers. The mouse looks around while a button is
+/// held, or all the time with a grabbed cursor.
+pub struct MouseLook {
+    is_rotating: bool,
+    cursor_grabbed: bool,
+    cursor_grab_changed: bool,
+    rotate_horizontal: f32,
+    rotate_vertical: f32,
+}
+
+impl MouseLook {
+    pub fn new() -> Self {
+        Self {
+            is_rotating: false,
+            cursor_grabbed: false,
+            cursor_grab_changed: false,
+            rotate_horizontal: 0.0,
+            rotate_vertical: 0.0,
+        }
+    }
+
+    pub fn process_mouse_button(&mut self, button: MouseButton, state: ElementState) -> bool {
+        match button {
+            MouseButton::Left | MouseButton::Right => {
+                self.is_rotating = state == ElementState::Pressed;
                 true
             }
             _ => false,
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera) {
-        use cgmath::InnerSpace;
-        let forward = camera.target - camera.eye;
-        let forward_norm = forward.normalize();
-        let forward_mag = forward.magnitude();
-
-        // Prevents glitching when camera gets too close to the
-        // center of the scene.
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
-        }
-        if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
+    pub fn process_mouse_motion(&mut self, dx: f64, dy: f64) {
+        if self.is_rotating || self.cursor_grabbed {
+            self.rotate_horizontal += dx as f32;
+            self.rotate_vertical += dy as f32;
         }
+    }
 
-        let right = forward_norm.cross(camera.up);
+    pub fn is_cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed
+    }
 
-        // Redo radius calc in case the up/ down is pressed.
-        let forward = camera.target - camera.eye;
-        let forward_mag = forward.magnitude();
+    pub fn toggle_cursor_grab(&mut self) {
+        self.cursor_grabbed = !self.cursor_grabbed;
+        self.cursor_grab_changed = true;
+    }
 
-        if self.is_right_pressed {
-            // Rescale the distance between the target and eye so
-            // that it doesn't change. The eye therefore still
-            // lies on the circle made by the target and eye.
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
-        }
-        if self.is_left_pressed {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
+    pub fn take_cursor_grab_change(&mut self) -> Option<bool> {
+        if self.cursor_grab_changed {
+            self.cursor_grab_changed = false;
+            Some(self.cursor_grabbed)
+        } else {
+            None
         }
     }
+
+    /// Returns the horizontal and vertical motion in pixels since the last call.
+    pub fn take_rotation(&mut self) -> (f32, f32) {
+        let rotation = (self.rotate_horizontal, self.rotate_vertical);
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+        rotation
+    }
+}
+
+pub fn scroll_lines(delta: &MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, lines) => *lines,
+        MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grab_toggle_is_reported_once() {
+        let mut mouse_look = MouseLook::new();
+        mouse_look.toggle_cursor_grab();
+
+        assert_eq!(mouse_look.take_cursor_grab_change(), Some(true));
+        assert_eq!(mouse_look.take_cursor_grab_change(), None);
+    }
+
+    #[test]
+    fn grabbed_cursor_turns_without_a_button() {
+        let mut mouse_look = MouseLook::new();
+        mouse_look.process_mouse_motion(5.0, 0.0);
+        assert_eq!(mouse_look.take_rotation(), (0.0, 0.0));
+
+        mouse_look.toggle_cursor_grab();
+        mouse_look.process_mouse_motion(5.0, -2.0);
+        mouse_look.process_mouse_motion(1.0, 0.0);
+        assert_eq!(mouse_look.take_rotation(), (6.0, -2.0));
+        assert_eq!(mouse_look.take_rotation(), (0.0, 0.0));
+    }
 }
@@ -0,0 +1,196 @@
+use crate::render::camera::camera::Camera;
+use crate::render::camera::camera_controller::{
+    scroll_lines, CameraController, CameraMode, MouseLook, MAX_PITCH, MOUSE_SENSITIVITY,
+};
+use cgmath::{InnerSpace, Vector3};
+use winit::event::{ElementState, KeyboardInput, MouseScrollDelta, VirtualKeyCode};
+
+/// Speed factor while the sprint key is held.
+const SPRINT_MULTIPLIER: f32 = 4.0;
+/// Units per second the eye moves while a movement key is held.
+pub const DEFAULT_SPEED: f32 = 40.0;
+/// Speed factor per line scrolled.
+const SPEED_PER_LINE: f32 = 1.1;
+
+/// Moves the eye freely, turning with the mouse and strafing relative to where it looks.
+/// The target stays in front of the eye at its original distance, so switching back to
+/// orbiting circles around what is in view.
+pub struct FlyCameraController {
+    speed: f32,
+    is_up_pressed: bool,
+    is_down_pressed: bool,
+    is_forward_pressed: bool,
+    is_backward_pressed: bool,
+    is_left_pressed: bool,
+    is_right_pressed: bool,
+    is_sprint_pressed: bool,
+    mouse_look: MouseLook,
+}
+
+impl FlyCameraController {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            is_up_pressed: false,
+            is_down_pressed: false,
+            is_forward_pressed: false,
+            is_backward_pressed: false,
+            is_left_pressed: false,
+            is_right_pressed: false,
+            is_sprint_pressed: false,
+            mouse_look: MouseLook::new(),
+        }
+    }
+}
+
+impl CameraController for FlyCameraController {
+    fn mode(&self) -> CameraMode {
+        CameraMode::Fly
+    }
+
+    fn mouse_look(&mut self) -> &mut MouseLook {
+        &mut self.mouse_look
+    }
+
+    fn process_events(&mut self, event: &KeyboardInput) -> bool {
+        let keycode = match event.virtual_keycode {
+            Some(keycode) => keycode,
+            None => return false,
+        };
+        let is_pressed = event.state == ElementState::Pressed;
+
+        match keycode {
+            VirtualKeyCode::Space => self.is_up_pressed = is_pressed,
+            VirtualKeyCode::LShift => self.is_down_pressed = is_pressed,
+            VirtualKeyCode::LControl => self.is_sprint_pressed = is_pressed,
+            VirtualKeyCode::W | VirtualKeyCode::Up => self.is_forward_pressed = is_pressed,
+            VirtualKeyCode::A | VirtualKeyCode::Left => self.is_left_pressed = is_pressed,
+            VirtualKeyCode::S | VirtualKeyCode::Down => self.is_backward_pressed = is_pressed,
+            VirtualKeyCode::D | VirtualKeyCode::Right => self.is_right_pressed = is_pressed,
+            VirtualKeyCode::M => {
+                if is_pressed {
+                    self.mouse_look.toggle_cursor_grab();
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Scrolling changes how fast the camera flies.
+    fn process_scroll(&mut self, delta: &MouseScrollDelta) {
+        self.speed *= SPEED_PER_LINE.powf(scroll_lines(delta));
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, delta: f32) {
+        let offset = camera.target - camera.eye;
+        let distance = offset.magnitude();
+        let forward = offset / distance;
+
+        let (rotate_horizontal, rotate_vertical) = self.mouse_look.take_rotation();
+        let yaw = forward.x.atan2(forward.z) - rotate_horizontal * MOUSE_SENSITIVITY;
+        let pitch =
+            (forward.y.asin() - rotate_vertical * MOUSE_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+        let forward = Vector3::new(
+            pitch.cos() * yaw.sin(),
+            pitch.sin(),
+            pitch.cos() * yaw.cos(),
+        );
+        let right = forward.cross(camera.up).normalize();
+
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let movement = forward * axis(self.is_forward_pressed, self.is_backward_pressed)
+            + right * axis(self.is_right_pressed, self.is_left_pressed)
+            + camera.up * axis(self.is_up_pressed, self.is_down_pressed);
+        let speed = if self.is_sprint_pressed {
+            self.speed * SPRINT_MULTIPLIER
+        } else {
+            self.speed
+        };
+
+        camera.eye += movement * speed * delta;
+        camera.target = camera.eye + forward * distance;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{assert_relative_eq, Point3};
+    use winit::event::MouseButton;
+
+    use super::*;
+    use crate::test_util::key;
+
+    fn camera() -> Camera {
+        Camera {
+            eye: Point3::new(0.0, 0.0, 10.0),
+            target: Point3::new(0.0, 0.0, 0.0),
+            up: Vector3::unit_y(),
+            ..Camera::default()
+        }
+    }
+
+    #[test]
+    fn flies_through_the_target() {
+        let mut controller = FlyCameraController::new(4.0);
+        let mut camera = camera();
+
+        controller.process_events(&key(VirtualKeyCode::W, ElementState::Pressed));
+        for _ in 0..5 {
+            controller.update_camera(&mut camera, 1.0);
+        }
+
+        assert_relative_eq!(camera.eye, Point3::new(0.0, 0.0, -10.0), epsilon = 1e-4);
+        assert_relative_eq!(camera.target, Point3::new(0.0, 0.0, -20.0), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn distance_covered_does_not_depend_on_the_frame_rate() {
+        let mut slow = FlyCameraController::new(6.0);
+        let mut fast = FlyCameraController::new(6.0);
+        let mut slow_camera = camera();
+        let mut fast_camera = camera();
+
+        let forward = key(VirtualKeyCode::W, ElementState::Pressed);
+        slow.process_events(&forward);
+        fast.process_events(&forward);
+        for _ in 0..30 {
+            slow.update_camera(&mut slow_camera, 1.0 / 30.0);
+        }
+        for _ in 0..144 {
+            fast.update_camera(&mut fast_camera, 1.0 / 144.0);
+        }
+
+        assert_relative_eq!(slow_camera.eye, Point3::new(0.0, 0.0, 4.0), epsilon = 1e-3);
+        assert_relative_eq!(fast_camera.eye, slow_camera.eye, epsilon = 1e-3);
+    }
+
+    #[test]
+    fn strafes_climbs_and_sprints() {
+        let mut controller = FlyCameraController::new(1.0);
+        let mut camera = camera();
+
+        controller.process_events(&key(VirtualKeyCode::D, ElementState::Pressed));
+        controller.process_events(&key(VirtualKeyCode::Space, ElementState::Pressed));
+        controller.process_events(&key(VirtualKeyCode::LControl, ElementState::Pressed));
+        controller.update_camera(&mut camera, 1.0);
+
+        let step = SPRINT_MULTIPLIER;
+        assert_relative_eq!(camera.eye, Point3::new(step, step, 10.0), epsilon = 1e-4);
+    }
+
+    #[test]
+    fn mouse_turns_the_view_in_place() {
+        let mut controller = FlyCameraController::new(1.0);
+        let mut camera = camera();
+
+        controller.process_mouse_button(MouseButton::Right, ElementState::Pressed);
+        controller
+            .process_mouse_motion(std::f64::consts::FRAC_PI_2 / MOUSE_SENSITIVITY as f64, 0.0);
+        controller.update_camera(&mut camera, 1.0);
+
+        // A quarter turn to the right looks down +X
+        assert_relative_eq!(camera.eye, Point3::new(0.0, 0.0, 10.0));
+        assert_relative_eq!(camera.target, Point3::new(10.0, 0.0, 10.0), epsilon = 1e-3);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::render::camera::camera::{Camera, Projection};
+use crate::render::camera::camera_controller::{
+    scroll_lines, CameraController, CameraMode, MouseLook, MAX_PITCH, MOUSE_SENSITIVITY,
+};
+use winit::event::{ElementState, KeyboardInput, MouseScrollDelta, VirtualKeyCode};
+
+/// Fraction of the distance to the target covered by one line of scrolling.
+const ZOOM_PER_LINE: f32 = 0.1;
+const MIN_DISTANCE: f32 = 1.0;
+/// Units per second the eye moves while a movement key is held.
+pub const DEFAULT_SPEED: f32 = 30.0;
+
+/// Circles the eye around `Camera::target`, the eye never passes the target.
+pub struct OrbitCameraController {
+    speed: f32,
+    is_up_pressed: bool,
+    is_down_pressed: bool,
+    is_forward_pressed: bool,
+    is_backward_pressed: bool,
+    is_left_pressed: bool,
+    is_right_pressed: bool,
+    mouse_look: MouseLook,
+    scroll: f32,
+}
+
+impl OrbitCameraController {
+    pub fn new(speed: f32) -> Self {
+        Self {
+            speed,
+            is_up_pressed: false,
+            is_down_pressed: false,
+            is_forward_pressed: false,
+            is_backward_pressed: false,
+            is_left_pressed: false,
+            is_right_pressed: false,
+            mouse_look: MouseLook::new(),
+            scroll: 0.0,
+        }
+    }
+}
+
+impl CameraController for OrbitCameraController {
+    fn mode(&self) -> CameraMode {
+        CameraMode::Orbit
+    }
+
+    fn mouse_look(&mut self) -> &mut MouseLook {
+        &mut self.mouse_look
+    }
+
+    fn process_events(&mut self, event: &KeyboardInput) -> bool {
+        if event.virtual_keycode.is_none() {
+            return false;
+        }
+
+        let is_pressed = event.state == ElementState::Pressed;
+        let keycode = event.virtual_keycode.unwrap();
+
+        match keycode {
+            VirtualKeyCode::Space => {
+                self.is_up_pressed = is_pressed;
+                true
+            }
+            VirtualKeyCode::LShift => {
+                self.is_down_pressed = is_pressed;
+                true
+            }
+            VirtualKeyCode::W | VirtualKeyCode::Up => {
+                self.is_forward_pressed = is_pressed;
+                true
+            }
+            VirtualKeyCode::A | VirtualKeyCode::Left => {
+                self.is_left_pressed = is_pressed;
+                true
+            }
+            VirtualKeyCode::S | VirtualKeyCode::Down => {
+                self.is_backward_pressed = is_pressed;
+                true
+            }
+            VirtualKeyCode::D | VirtualKeyCode::Right => {
+                self.is_right_pressed = is_pressed;
+                true
+            }
+            VirtualKeyCode::M => {
+                if is_pressed {
+                    self.mouse_look.toggle_cursor_grab();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn process_scroll(&mut self, delta: &MouseScrollDelta) {
+        self.scroll += scroll_lines(delta);
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, delta: f32) {
+        use cgmath::InnerSpace;
+        let step = self.speed * delta;
+        let forward = camera.target - camera.eye;
+        let forward_norm = forward.normalize();
+        let forward_mag = forward.magnitude();
+
+        // Prevents glitching when camera gets too close to the
+        // center of the scene.
+        if self.is_forward_pressed && forward_mag > step {
+            camera.eye += forward_norm * step;
+        }
+        if self.is_backward_pressed {
+            camera.eye -= forward_norm * step;
+        }
+
+        let right = forward_norm.cross(camera.up);
+
+        // Redo radius calc in case the up/ down is pressed.
+        let forward = camera.target - camera.eye;
+        let forward_mag = forward.magnitude();
+
+        if self.is_right_pressed {
+            // Rescale the distance between the target and eye so
+            // that it doesn't change. The eye therefore still
+            // lies on the circle made by the target and eye.
+            camera.eye = camera.target - (forward + right * step).normalize() * forward_mag;
+        }
+        if self.is_left_pressed {
+            camera.eye = camera.target - (forward - right * step).normalize() * forward_mag;
+        }
+
+        self.orbit(camera);
+    }
+}
+
+impl OrbitCameraController {
+    /// Turns the eye around the target by the mouse motion and moves it closer or
+    /// farther by the scrolling gathered since the last update.
+    fn orbit(&mut self, camera: &mut Camera) {
+        use cgmath::InnerSpace;
+        let (rotate_horizontal, rotate_vertical) = self.mouse_look.take_rotation();
+        if rotate_horizontal == 0.0 && rotate_vertical == 0.0 && self.scroll == 0.0 {
+            return;
+        }
+
+        let offset = camera.eye - camera.target;
+        let distance = offset.magnitude();
+        let yaw = offset.x.atan2(offset.z) - rotate_horizontal * MOUSE_SENSITIVITY;
+        let pitch = ((offset.y / distance).asin() + rotate_vertical * MOUSE_SENSITIVITY)
+            .clamp(-MAX_PITCH, MAX_PITCH);
+        let zoomed = (distance * (1.0 - self.scroll * ZOOM_PER_LINE)).max(MIN_DISTANCE);
+        // Distance doesn't change the size of an orthographic view, so it zooms as well
+        if let Projection::Orthographic { height } = &mut camera.projection {
+            *height *= zoomed / distance;
+        }
+        let distance = zoomed;
+
+        camera.eye = camera.target
+            + cgmath::Vector3::new(
+                pitch.cos() * yaw.sin(),
+                pitch.sin(),
+                pitch.cos() * yaw.cos(),
+            ) * distance;
+
+        self.scroll = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{assert_relative_eq, InnerSpace, Point3};
+    use winit::event::MouseButton;
+
+    use super::*;
+    use crate::test_util::key;
+
+    fn camera() -> Camera {
+        Camera {
+            eye: Point3::new(0.0, 0.0, 10.0),
+            target: Point3::new(0.0, 0.0, 0.0),
+            up: cgmath::Vector3::unit_y(),
+            ..Camera::default()
+        }
+    }
+
+    #[test]
+    fn mouse_motion_only_turns_while_a_button_is_held() {
+        let mut controller = OrbitCameraController::new(1.0);
+        let mut camera = camera();
+
+        controller.process_mouse_motion(100.0, 0.0);
+        controller.update_camera(&mut camera, 1.0);
+        assert_eq!(camera.eye, Point3::new(0.0, 0.0, 10.0));
+
+        controller.process_mouse_button(MouseButton::Right, ElementState::Pressed);
+        controller.process_mouse_motion(100.0, 0.0);
+        controller.update_camera(&mut camera, 1.0);
+        assert!(camera.eye.x < 0.0);
+        assert_relative_eq!(
+            (camera.eye - camera.target).magnitude(),
+            10.0,
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn pitch_stops_short_of_the_poles() {
+        let mut controller = OrbitCameraController::new(1.0);
+        let mut camera = camera();
+
+        controller.process_mouse_button(MouseButton::Left, ElementState::Pressed);
+        controller.process_mouse_motion(0.0, 1.0e6);
+        controller.update_camera(&mut camera, 1.0);
+
+        let direction = (camera.eye - camera.target).normalize();
+        assert_relative_eq!(direction.y, MAX_PITCH.sin(), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn scrolling_zooms_towards_the_target_but_not_past_it() {
+        let mut controller = OrbitCameraController::new(1.0);
+        let mut camera = camera();
+
+        controller.process_scroll(&MouseScrollDelta::LineDelta(0.0, 2.0));
+        controller.update_camera(&mut camera, 1.0);
+        assert_relative_eq!(camera.eye, Point3::new(0.0, 0.0, 8.0), epsilon = 1e-4);
+
+        controller.process_scroll(&MouseScrollDelta::LineDelta(0.0, 100.0));
+        controller.update_camera(&mut camera, 1.0);
+        assert_relative_eq!(camera.eye.z, MIN_DISTANCE, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn m_toggles_the_cursor_grab() {
+        let mut controller = OrbitCameraController::new(1.0);
+
+        assert!(controller.process_events(&key(VirtualKeyCode::M, ElementState::Pressed)));
+        assert!(controller.process_events(&key(VirtualKeyCode::M, ElementState::Released)));
+        assert_eq!(controller.take_cursor_grab_change(), Some(true));
+    }
+
+    #[test]
+    fn scrolling_zooms_orthographic_views() {
+        let mut controller = OrbitCameraController::new(1.0);
+        let mut camera = Camera {
+            projection: Projection::Orthographic { height: 20.0 },
+            ..camera()
+        };
+
+        controller.process_scroll(&MouseScrollDelta::LineDelta(0.0, 5.0));
+        controller.update_camera(&mut camera, 1.0);
+        assert_eq!(camera.projection, Projection::Orthographic { height: 10.0 });
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::num::NonZeroU32;
+
+use image::RgbaImage;
+use wgpu::BufferAddress;
+
+use crate::render::render_state::RenderTarget;
+use crate::render::renderer::encode_scene;
+use crate::render::texture::Texture;
+use crate::RenderState;
+
+const BYTES_PER_PIXEL: u32 = 4;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    /// The color target uses a format that can't be turned into 8 bit RGBA.
+    UnsupportedFormat(wgpu::TextureFormat),
+    Map(wgpu::BufferAsyncError),
+    Save(image::ImageError),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::UnsupportedFormat(format) => {
+                write!(f, "cannot capture a {:?} color target", format)
+            }
+            CaptureError::Map(e) => write!(f, "could not read back the frame: {}", e),
+            CaptureError::Save(e) => write!(f, "could not save the frame: {}", e),
+        }
+    }
+}
+
+impl From<image::ImageError> for CaptureError {
+    fn from(e: image::ImageError) -> Self {
+        CaptureError::Save(e)
+    }
+}
+
+/// Copies the color target into an image. Surface textures can't be copied from, so
+/// in a window the scene is drawn once more into a copyable texture of the same format.
+pub fn capture_frame(state: &mut RenderState) -> Result<RgbaImage, CaptureError> {
+    let format = state.config.format;
+    let swap_red_blue = match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        format => return Err(CaptureError::UnsupportedFormat(format)),
+    };
+
+    let width = state.config.width;
+    let height = state.config.height;
+    let padded_bytes_per_row = padded_bytes_per_row(width);
+
+    let buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Capture Buffer"),
+        size: (padded_bytes_per_row * height) as BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = state
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Capture Encoder"),
+        });
+
+    let scratch = match state.target {
+        RenderTarget::Surface(_) => {
+            let config = wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                ..state.config.clone()
+            };
+            Some(Texture::create_render_target(&state.device, &config))
+        }
+        RenderTarget::Offscreen(_) => None,
+    };
+    if let Some(scratch) = &scratch {
+        encode_scene(state, &scratch.view, &mut encoder);
+    }
+    let texture = match (&scratch, &state.target) {
+        (Some(scratch), _) => &scratch.texture,
+        (None, RenderTarget::Offscreen(target)) => &target.texture,
+        (None, RenderTarget::Surface(_)) => unreachable!(),
+    };
+
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                rows_per_image: NonZeroU32::new(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    state.queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    state.device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(mapping).map_err(CaptureError::Map)?;
+
+    let mut pixels = unpad_rows(&slice.get_mapped_range(), width, height);
+    buffer.unmap();
+
+    if swap_red_blue {
+        bgra_to_rgba(&mut pixels);
+    }
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("unpadded rows match the frame size"))
+}
+
+/// Texture to buffer copies need every row to start on a `COPY_BYTES_PER_ROW_ALIGNMENT` boundary.
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let unpadded = width * BYTES_PER_PIXEL;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
+
+fn unpad_rows(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row = (width * BYTES_PER_PIXEL) as usize;
+    data.chunks(padded_bytes_per_row(width) as usize)
+        .take(height as usize)
+        .flat_map(|padded| &padded[..row])
+        .copied()
+        .collect()
+}
+
+fn bgra_to_rgba(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(BYTES_PER_PIXEL as usize) {
+        pixel.swap(0, 2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_padded_to_the_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+        assert_eq!(padded_bytes_per_row(1920), 7680);
+    }
+
+    #[test]
+    fn unpadding_drops_the_tail_of_every_row() {
+        let padded = padded_bytes_per_row(2) as usize;
+        let mut data = vec![0xff; padded * 2];
+        data[..8].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        data[padded..padded + 8].copy_from_slice(&[9, 10, 11, 12, 13, 14, 15, 16]);
+
+        assert_eq!(unpad_rows(&data, 2, 2), (1..=16).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn bgra_pixels_are_swizzled_to_rgba() {
+        let mut pixels = vec![3, 2, 1, 255, 30, 20, 10, 128];
+        bgra_to_rgba(&mut pixels);
+        assert_eq!(pixels, vec![1, 2, 3, 255, 10, 20, 30, 128]);
+    }
+}
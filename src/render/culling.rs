@@ -0,0 +1,245 @@
+use std::ops::Range;
+
+use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+
+use crate::render::instance::{Instance, InstanceRaw};
+use crate::render::instance_handler::InstanceHandler;
+use crate::render::lib::Vertex;
+use crate::render::mesh::MeshId;
+use crate::render::mesh_registry::MeshRegistry;
+
+/// A sphere enclosing every vertex of a mesh, in the mesh's own space.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Centers the sphere on the vertices' bounding box, which is tight enough for the
+    /// boxy meshes drawn here and much cheaper than a minimal sphere.
+    pub fn from_vertices(vertices: &[Vertex]) -> BoundingSphere {
+        if vertices.is_empty() {
+            return BoundingSphere {
+                center: Vector3::new(0.0, 0.0, 0.0),
+                radius: 0.0,
+            };
+        }
+
+        let mut min = Vector3::from(vertices[0].position);
+        let mut max = min;
+        for vertex in vertices.iter() {
+            let position = Vector3::from(vertex.position);
+            min = Vector3::new(
+                min.x.min(position.x),
+                min.y.min(position.y),
+                min.z.min(position.z),
+            );
+            max = Vector3::new(
+                max.x.max(position.x),
+                max.y.max(position.y),
+                max.z.max(position.z),
+            );
+        }
+
+        let center = (min + max) / 2.0;
+        let radius = vertices
+            .iter()
+            .map(|vertex| (Vector3::from(vertex.position) - center).magnitude())
+            .fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    /// The sphere moved into world space by `instance`, scaled by its largest axis.
+    pub fn transformed(&self, instance: &Instance) -> BoundingSphere {
+        let scaled = Vector3::new(
+            self.center.x * instance.scale.x,
+            self.center.y * instance.scale.y,
+            self.center.z * instance.scale.z,
+        );
+        let scale = instance
+            .scale
+            .x
+            .abs()
+            .max(instance.scale.y.abs())
+            .max(instance.scale.z.abs());
+        BoundingSphere {
+            center: instance.position + instance.rotation * scaled,
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// A plane facing into the frustum, points with `normal · p + distance >= 0` are inside.
+#[derive(Copy, Clone, Debug)]
+struct Plane {
+    normal: Vector3<f32>,
+    distance: f32,
+}
+
+impl Plane {
+    fn from_row(row: Vector4<f32>) -> Plane {
+        let normal = row.truncate();
+        let length = normal.magnitude();
+        Plane {
+            normal: normal / length,
+            distance: row.w / length,
+        }
+    }
+}
+
+/// The six planes bounding what the camera sees, in world space.
+pub struct Frustum {
+    planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view projection matrix with wgpu's clip space, where
+    /// depth goes from 0 to 1 rather than -1 to 1.
+    pub fn from_view_proj(view_proj: &Matrix4<f32>) -> Frustum {
+        let row = |i: usize| {
+            Vector4::new(
+                view_proj.x[i],
+                view_proj.y[i],
+                view_proj.z[i],
+                view_proj.w[i],
+            )
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Frustum {
+            planes: [
+                Plane::from_row(w + x),
+                Plane::from_row(w - x),
+                Plane::from_row(w + y),
+                Plane::from_row(w - y),
+                Plane::from_row(z),
+                Plane::from_row(w - z),
+            ],
+        }
+    }
+
+    /// The planes as `[normal.x, normal.y, normal.z, distance]`, for the culling shader.
+    pub fn planes(&self) -> [[f32; 4]; 6] {
+        self.planes
+            .map(|plane| plane.normal.extend(plane.distance).into())
+    }
+
+    /// True unless the sphere lies entirely outside one of the planes. Spheres near a
+    /// corner can pass without being visible, they are only drawn for nothing.
+    pub fn intersects(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.normal.dot(sphere.center) + plane.distance >= -sphere.radius)
+    }
+}
+
+/// Instances that passed culling packed back to back, with the range each mesh draws.
+pub(crate) struct VisibleInstances {
+    pub(crate) raws: Vec<InstanceRaw>,
+    pub(crate) draws: Vec<(MeshId, Range<u32>)>,
+}
+
+/// Collects the instances whose bounds reach into `frustum`, grouped by mesh.
+pub(crate) fn cull_instances(
+    handler: &InstanceHandler,
+    mesh_registry: &MeshRegistry,
+    frustum: &Frustum,
+) -> VisibleInstances {
+    let mut raws = Vec::new();
+    let mut draws = Vec::new();
+
+    for mesh_id in handler.region_order.iter() {
+        let mesh = match mesh_registry.get(*mesh_id) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+
+        let start = raws.len() as u32;
+        for instance in handler.regions[mesh_id].instances.iter().flatten() {
+            if frustum.intersects(&mesh.bounds.transformed(instance)) {
+                raws.push(instance.to_raw());
+            }
+        }
+        let end = raws.len() as u32;
+        if end > start {
+            draws.push((*mesh_id, start..end));
+        }
+    }
+
+    VisibleInstances { raws, draws }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Point3};
+
+    use super::*;
+    use crate::data::{CUBE, CUBE_INDICES, TRIANGLE, TRIANGLE_INDICES};
+    use crate::render::lib::OPENGL_TO_WGPU_MATRIX;
+
+    /// Looks down -Z from the origin, with a 90 degree field of view and depth 1 to 100.
+    fn frustum() -> Frustum {
+        let view = Matrix4::look_at_rh(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vector3::unit_y(),
+        );
+        let proj = cgmath::perspective(Deg(90.0), 1.0, 1.0, 100.0);
+        Frustum::from_view_proj(&(OPENGL_TO_WGPU_MATRIX * proj * view))
+    }
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> BoundingSphere {
+        BoundingSphere {
+            center: Vector3::new(x, y, z),
+            radius,
+        }
+    }
+
+    #[test]
+    fn spheres_are_tested_against_every_plane() {
+        let frustum = frustum();
+
+        assert!(frustum.intersects(&sphere(0.0, 0.0, -10.0, 0.5)));
+        // Behind the camera, past the far plane, and off to the sides
+        assert!(!frustum.intersects(&sphere(0.0, 0.0, 5.0, 0.5)));
+        assert!(!frustum.intersects(&sphere(0.0, 0.0, -102.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(12.0, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(0.0, -12.0, -10.0, 1.0)));
+        // Straddling the left plane and the near plane
+        assert!(frustum.intersects(&sphere(-10.5, 0.0, -10.0, 1.0)));
+        assert!(frustum.intersects(&sphere(0.0, 0.0, -0.5, 1.0)));
+    }
+
+    #[test]
+    fn bounds_follow_the_instance_transform() {
+        let bounds = BoundingSphere::from_vertices(CUBE);
+        assert_eq!(bounds.center, Vector3::new(0.0, 0.0, 0.0));
+
+        let mut cube = Instance::new(MeshId(0), Vector3::new(3.0, 0.0, 0.0));
+        cube.scale = Vector3::new(1.0, 4.0, 2.0);
+        let transformed = bounds.transformed(&cube);
+        assert_eq!(transformed.center, Vector3::new(3.0, 0.0, 0.0));
+        assert!((transformed.radius - bounds.radius * 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn visible_instances_are_packed_per_mesh() {
+        let mut mesh_registry = MeshRegistry::new();
+        let cube = mesh_registry.register("cube", CUBE, CUBE_INDICES);
+        let triangle = mesh_registry.register("triangle", TRIANGLE, TRIANGLE_INDICES);
+
+        let mut handler = InstanceHandler::new();
+        handler.add(Instance::new(cube, Vector3::new(0.0, 0.0, -10.0)));
+        handler.add(Instance::new(cube, Vector3::new(0.0, 0.0, 10.0)));
+        let removed = handler.add(Instance::new(cube, Vector3::new(0.0, 0.0, -20.0)));
+        handler.add(Instance::new(cube, Vector3::new(1.0, 0.0, -30.0)));
+        handler.add(Instance::new(triangle, Vector3::new(50.0, 0.0, -10.0)));
+        handler.add(Instance::new(triangle, Vector3::new(0.0, 2.0, -5.0)));
+        handler.remove(removed.unwrap());
+
+        let visible = cull_instances(&handler, &mesh_registry, &frustum());
+
+        assert_eq!(visible.raws.len(), 3);
+        assert_eq!(visible.draws, vec![(cube, 0..2), (triangle, 2..3)]);
+    }
+}
@@ -0,0 +1,293 @@
+//! Golden image tests: a described scene is rendered headless on the fallback
+//! (software) adapter and compared against a reference PNG in `tests/golden`. On a
+//! mismatch the actual frame and a diff image are written to `target/golden`.
+//!
+//! The scene tests are ignored by default since not every machine has a fallback
+//! adapter, run them with `cargo test -- --ignored`. A missing adapter or reference
+//! fails the test, `UPDATE_GOLDEN=1` records every reference from the current render.
+
+use std::path::PathBuf;
+
+use cgmath::{Point3, Quaternion, Rotation3, Vector3};
+use image::{Rgba, RgbaImage};
+use winit::dpi::PhysicalSize;
+
+use crate::render::camera::camera::Projection;
+use crate::render::instance::{Instance, Material};
+use crate::render::lib::RenderSettings;
+use crate::render::render_state_factory::create_headless_render_state;
+use crate::render::texture_registry::TextureId;
+
+/// Largest difference allowed in any channel before a pixel counts as changed, this
+/// absorbs rasterization differences between adapters.
+pub const DEFAULT_TOLERANCE: u8 = 8;
+
+pub struct SceneInstance {
+    pub mesh: &'static str,
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub color: [f32; 3],
+    pub material: Material,
+    /// Name of a texture registered by `build_render_state`.
+    pub texture: Option<&'static str>,
+}
+
+impl SceneInstance {
+    pub fn new(mesh: &'static str, position: Vector3<f32>) -> Self {
+        SceneInstance {
+            mesh,
+            position,
+            rotation: Quaternion::from_angle_y(cgmath::Deg(0.0)),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            color: [1.0, 1.0, 1.0],
+            material: Material::default(),
+            texture: None,
+        }
+    }
+}
+
+pub struct SceneDescription {
+    pub size: PhysicalSize<u32>,
+    pub settings: RenderSettings,
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub projection: Projection,
+    pub instances: Vec<SceneInstance>,
+}
+
+impl Default for SceneDescription {
+    fn default() -> Self {
+        SceneDescription {
+            size: PhysicalSize::new(256, 256),
+            settings: RenderSettings::default(),
+            eye: Point3::new(3.0, 3.0, 6.0),
+            target: Point3::new(0.0, 0.0, 0.0),
+            projection: Projection::Perspective { fovy: 45.0 },
+            instances: vec![],
+        }
+    }
+}
+
+/// Renders one frame of `scene` on the fallback adapter, so references don't depend
+/// on the GPU, or `None` when there is no such adapter.
+pub fn render_scene(scene: &SceneDescription) -> Option<RgbaImage> {
+    let settings = RenderSettings {
+        force_fallback_adapter: true,
+        ..scene.settings
+    };
+    let mut state = pollster::block_on(create_headless_render_state(scene.size, settings))?;
+
+    state.camera.eye = scene.eye;
+    state.camera.target = scene.target;
+    state.camera.projection = scene.projection;
+    state.camera.update();
+
+    for instance in scene.instances.iter() {
+        let mesh = state
+            .mesh_registry
+            .find(instance.mesh)
+            .unwrap_or_else(|| panic!("Unknown mesh {}", instance.mesh));
+        let texture = match instance.texture {
+            Some(name) => state
+                .texture_registry
+                .find(name)
+                .unwrap_or_else(|| panic!("Unknown texture {}", name)),
+            None => TextureId::NONE,
+        };
+        state.instance_handler.add(Instance {
+            mesh,
+            position: instance.position,
+            rotation: instance.rotation,
+            scale: instance.scale,
+            color: instance.color,
+            material: instance.material,
+            texture,
+        });
+    }
+
+    state.update();
+    state.render().expect("Offscreen targets can't be lost");
+    Some(state.capture_frame().expect("Offscreen target is RGBA"))
+}
+
+pub struct Comparison {
+    pub mismatched: usize,
+    pub diff: RgbaImage,
+}
+
+/// Compares two frames channel by channel. The diff shows matching pixels as a faded
+/// copy of `expected` and mismatched ones in red.
+pub fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> Comparison {
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "Frame size differs from the reference"
+    );
+
+    let mut mismatched = 0;
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    for ((a, e), d) in actual
+        .pixels()
+        .zip(expected.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let differs =
+            a.0.iter()
+                .zip(e.0.iter())
+                .any(|(a, e)| (*a as i16 - *e as i16).unsigned_abs() > tolerance as u16);
+        *d = if differs {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([e[0] / 4, e[1] / 4, e[2] / 4, 255])
+        };
+    }
+
+    Comparison { mismatched, diff }
+}
+
+/// Renders `scene` and panics if it doesn't match `tests/golden/<name>.png`, or
+/// records the reference when `UPDATE_GOLDEN=1` is set.
+pub fn assert_golden(name: &str, scene: &SceneDescription, tolerance: u8) {
+    let actual = match render_scene(scene) {
+        Some(actual) => actual,
+        None => panic!("No fallback adapter to render golden test {} with", name),
+    };
+
+    let reference = golden_dir().join(format!("{}.png", name));
+    if env_flag("UPDATE_GOLDEN") {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        actual.save(&reference).unwrap();
+        eprintln!("Recorded reference {}", reference.display());
+        return;
+    }
+    if !reference.exists() {
+        panic!(
+            "Missing reference {}, run with UPDATE_GOLDEN=1 to record it",
+            reference.display()
+        );
+    }
+
+    let expected = image::open(&reference).unwrap().into_rgba8();
+    let comparison = compare(&actual, &expected, tolerance);
+    if comparison.mismatched > 0 {
+        let out = output_dir();
+        std::fs::create_dir_all(&out).unwrap();
+        let actual_path = out.join(format!("{}.actual.png", name));
+        let diff_path = out.join(format!("{}.diff.png", name));
+        actual.save(&actual_path).unwrap();
+        comparison.diff.save(&diff_path).unwrap();
+        panic!(
+            "{} pixels differ from {}, see {} and {}",
+            comparison.mismatched,
+            reference.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|value| value == "1")
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"))
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/target/golden"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(4, 4, Rgba(color))
+    }
+
+    #[test]
+    fn differences_within_tolerance_match() {
+        let comparison = compare(&solid([100, 100, 100, 255]), &solid([108, 92, 100, 255]), 8);
+        assert_eq!(comparison.mismatched, 0);
+    }
+
+    #[test]
+    fn differences_above_tolerance_are_marked_red() {
+        let expected = solid([100, 100, 100, 255]);
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 2, Rgba([100, 109, 100, 255]));
+
+        let comparison = compare(&actual, &expected, 8);
+        assert_eq!(comparison.mismatched, 1);
+        assert_eq!(*comparison.diff.get_pixel(1, 2), Rgba([255, 0, 0, 255]));
+        assert_eq!(*comparison.diff.get_pixel(0, 0), Rgba([25, 25, 25, 255]));
+    }
+
+    #[test]
+    #[ignore = "needs a fallback adapter"]
+    fn cube_and_triangle_match_golden() {
+        let scene = SceneDescription {
+            instances: vec![
+                SceneInstance {
+                    texture: Some("checker"),
+                    ..SceneInstance::new("cube", Vector3::new(0.0, 0.0, 0.0))
+                },
+                SceneInstance {
+                    rotation: Quaternion::from_angle_y(cgmath::Deg(30.0)),
+                    ..SceneInstance::new("triangle", Vector3::new(-2.5, 0.0, 0.0))
+                },
+            ],
+            ..SceneDescription::default()
+        };
+        assert_golden("cube_and_triangle", &scene, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    #[ignore = "needs a fallback adapter"]
+    fn overlapping_cubes_match_golden() {
+        // The nearer cube has to hide the farther one, this catches depth test regressions
+        let scene = SceneDescription {
+            eye: Point3::new(0.0, 1.0, 8.0),
+            projection: Projection::Perspective { fovy: 60.0 },
+            instances: vec![
+                SceneInstance::new("cube", Vector3::new(0.0, 0.0, 0.0)),
+                SceneInstance {
+                    scale: Vector3::new(2.0, 2.0, 2.0),
+                    color: [0.2, 0.4, 1.0],
+                    ..SceneInstance::new("cube", Vector3::new(0.5, 0.0, -4.0))
+                },
+                SceneInstance {
+                    material: Material {
+                        emissive: [0.3, 0.3, 0.0],
+                        ..Material::default()
+                    },
+                    ..SceneInstance::new("pyramid", Vector3::new(-2.0, 0.0, 2.0))
+                },
+            ],
+            ..SceneDescription::default()
+        };
+        assert_golden("overlapping_cubes", &scene, DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    #[ignore = "needs a fallback adapter"]
+    fn orthographic_cubes_match_golden() {
+        // Without perspective the farther cube shows at the same size as the nearer one
+        let scene = SceneDescription {
+            eye: Point3::new(4.0, 4.0, 8.0),
+            projection: Projection::Orthographic { height: 6.0 },
+            instances: vec![
+                SceneInstance::new("cube", Vector3::new(-1.5, 0.0, 0.0)),
+                SceneInstance {
+                    color: [0.2, 1.0, 0.4],
+                    ..SceneInstance::new("cube", Vector3::new(1.5, 0.0, -3.0))
+                },
+            ],
+            ..SceneDescription::default()
+        };
+        assert_golden("orthographic_cubes", &scene, DEFAULT_TOLERANCE);
+    }
+}
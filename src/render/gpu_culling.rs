@@ -0,0 +1,346 @@
+use std::mem;
+use std::ops::Range;
+
+use wgpu::util::DeviceExt;
+use wgpu::BufferAddress;
+
+use crate::render::culling::Frustum;
+use crate::render::instance_handler::InstanceHandler;
+use crate::render::mesh::MeshId;
+use crate::render::mesh_registry::MeshRegistry;
+
+/// Invocations per workgroup of `cs_main` in cull.wgsl.
+const WORKGROUP_SIZE: u32 = 64;
+/// Regions the region and indirect buffers hold before they are reallocated.
+const INITIAL_CULL_REGION_CAPACITY: usize = 16;
+
+/// Matches `CullRegion` in cull.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct CullRegion {
+    center: [f32; 3],
+    radius: f32,
+    start: u32,
+    len: u32,
+    _padding: [u32; 2],
+}
+
+/// The arguments `draw_indexed_indirect` reads, the compute pass counts the instances.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+/// Culls instances on the GPU. A compute pass tests every slot of `instance_buffer`
+/// against the frustum and packs the visible ones at the start of their region in the
+/// visible instance buffer, counting them into one set of indirect draw arguments per
+/// mesh. The CPU never learns how many instances are visible.
+pub struct GpuCulling {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    frustum_buffer: wgpu::Buffer,
+    region_buffer: wgpu::Buffer,
+    indirect_buffer: wgpu::Buffer,
+    region_capacity: usize,
+}
+
+impl GpuCulling {
+    pub fn new(device: &wgpu::Device) -> GpuCulling {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Cull Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/cull.wgsl").into()),
+        });
+
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("cull_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, true),
+                storage(3, false),
+                storage(4, false),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cull Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cull Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+
+        let frustum_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Frustum Buffer"),
+            contents: bytemuck::cast_slice(&[[0.0f32; 4]; 6]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let (region_buffer, indirect_buffer) =
+            create_region_buffers(device, INITIAL_CULL_REGION_CAPACITY);
+
+        GpuCulling {
+            pipeline,
+            bind_group_layout,
+            frustum_buffer,
+            region_buffer,
+            indirect_buffer,
+            region_capacity: INITIAL_CULL_REGION_CAPACITY,
+        }
+    }
+
+    pub(crate) fn indirect_buffer(&self) -> &wgpu::Buffer {
+        &self.indirect_buffer
+    }
+
+    /// Runs the compute pass and returns the slots of the visible instance buffer each
+    /// mesh draws from, in the order of their indirect draw arguments.
+    /// `visible_instance_buffer` has to be at least as large as `instance_buffer`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn cull(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frustum: &Frustum,
+        handler: &InstanceHandler,
+        mesh_registry: &MeshRegistry,
+        instance_buffer: &wgpu::Buffer,
+        visible_instance_buffer: &wgpu::Buffer,
+    ) -> Vec<(MeshId, Range<u32>)> {
+        let CullInput {
+            regions,
+            draws,
+            draw_list,
+        } = build_cull_input(handler, mesh_registry);
+        if regions.is_empty() {
+            return draw_list;
+        }
+
+        if regions.len() > self.region_capacity {
+            self.region_capacity = regions.len().next_power_of_two();
+            let (region_buffer, indirect_buffer) =
+                create_region_buffers(device, self.region_capacity);
+            self.region_buffer = region_buffer;
+            self.indirect_buffer = indirect_buffer;
+        }
+        queue.write_buffer(
+            &self.frustum_buffer,
+            0,
+            bytemuck::cast_slice(&frustum.planes()),
+        );
+        queue.write_buffer(&self.region_buffer, 0, bytemuck::cast_slice(&regions));
+        // Resets the instance counts the compute pass adds to
+        queue.write_buffer(&self.indirect_buffer, 0, bytemuck::cast_slice(&draws));
+
+        // The instance buffers are replaced as they grow, so they are bound anew each frame
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("cull_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.frustum_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.region_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: visible_instance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.indirect_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let longest = regions.iter().map(|region| region.len).max().unwrap_or(0);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Cull Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Cull Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch(longest.div_ceil(WORKGROUP_SIZE), regions.len() as u32, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        draw_list
+    }
+}
+
+/// What the compute pass needs to know about the regions it culls.
+pub(crate) struct CullInput {
+    pub(crate) regions: Vec<CullRegion>,
+    /// Indirect draw arguments before any instance was counted, one per region.
+    pub(crate) draws: Vec<DrawIndexedIndirect>,
+    /// The mesh and slots of the visible instance buffer each region draws from.
+    pub(crate) draw_list: Vec<(MeshId, Range<u32>)>,
+}
+
+/// Describes every region with instances and a known mesh to the compute pass.
+pub(crate) fn build_cull_input(
+    handler: &InstanceHandler,
+    mesh_registry: &MeshRegistry,
+) -> CullInput {
+    let mut regions = Vec::new();
+    let mut draws = Vec::new();
+    let mut draw_list = Vec::new();
+
+    for mesh_id in handler.region_order.iter() {
+        let region = &handler.regions[mesh_id];
+        let mesh = match mesh_registry.get(*mesh_id) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        let slots = region.draw_range();
+        if slots.is_empty() {
+            continue;
+        }
+
+        regions.push(CullRegion {
+            center: mesh.bounds.center.into(),
+            radius: mesh.bounds.radius,
+            start: slots.start,
+            len: slots.end - slots.start,
+            _padding: [0; 2],
+        });
+        // The visible instance buffer is bound at the region's start, so no draw
+        // needs a first instance
+        draws.push(DrawIndexedIndirect {
+            index_count: mesh.index_range.end - mesh.index_range.start,
+            instance_count: 0,
+            first_index: mesh.index_range.start,
+            base_vertex: mesh.base_vertex,
+            first_instance: 0,
+        });
+        draw_list.push((*mesh_id, slots));
+    }
+
+    CullInput {
+        regions,
+        draws,
+        draw_list,
+    }
+}
+
+fn create_region_buffers(device: &wgpu::Device, capacity: usize) -> (wgpu::Buffer, wgpu::Buffer) {
+    let region_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Cull Region Buffer"),
+        size: (capacity * mem::size_of::<CullRegion>()) as BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let indirect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Indirect Buffer"),
+        size: (capacity * mem::size_of::<DrawIndexedIndirect>()) as BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::INDIRECT
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    (region_buffer, indirect_buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Zero};
+
+    use super::*;
+    use crate::data::{CUBE, CUBE_INDICES, TRIANGLE, TRIANGLE_INDICES};
+    use crate::render::instance::Instance;
+    use crate::render::instance_region::INITIAL_REGION_CAPACITY;
+
+    #[test]
+    fn layouts_match_the_shader() {
+        assert_eq!(mem::size_of::<CullRegion>(), 32);
+        assert_eq!(mem::size_of::<DrawIndexedIndirect>(), 20);
+        assert_eq!(
+            mem::size_of::<crate::render::instance::InstanceRaw>(),
+            34 * 4
+        );
+    }
+
+    #[test]
+    fn regions_describe_meshes_with_instances() {
+        let mut mesh_registry = MeshRegistry::new();
+        let cube = mesh_registry.register("cube", CUBE, CUBE_INDICES);
+        let triangle = mesh_registry.register("triangle", TRIANGLE, TRIANGLE_INDICES);
+
+        let mut handler = InstanceHandler::new();
+        handler.add(Instance::new(triangle, Vector3::zero()));
+        handler.add(Instance::new(cube, Vector3::zero()));
+        handler.add(Instance::new(cube, Vector3::zero()));
+
+        let CullInput {
+            regions,
+            draws,
+            draw_list,
+        } = build_cull_input(&handler, &mesh_registry);
+
+        assert_eq!(
+            draw_list,
+            vec![
+                (triangle, 0..1),
+                (
+                    cube,
+                    INITIAL_REGION_CAPACITY as u32..INITIAL_REGION_CAPACITY as u32 + 2
+                )
+            ]
+        );
+        assert_eq!(regions[1].start, INITIAL_REGION_CAPACITY as u32);
+        assert_eq!(regions[1].len, 2);
+        assert_eq!(
+            regions[1].radius,
+            mesh_registry.get(cube).unwrap().bounds.radius
+        );
+
+        let triangle_mesh = mesh_registry.get(triangle).unwrap();
+        assert_eq!(
+            draws[0],
+            DrawIndexedIndirect {
+                index_count: TRIANGLE_INDICES.len() as u32,
+                instance_count: 0,
+                first_index: triangle_mesh.index_range.start,
+                base_vertex: triangle_mesh.base_vertex,
+                first_instance: 0,
+            }
+        );
+    }
+}
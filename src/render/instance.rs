@@ -1,17 +1,49 @@
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
-pub enum InstanceType {
-    Empty,
-    Cube,
-    Triangle,
-}
+use cgmath::{Rotation3, SquareMatrix};
+
+use crate::render::mesh::MeshId;
+use crate::render::texture_registry::TextureId;
 
 pub struct Instance {
-    pub(crate) instance_type: InstanceType,
+    pub(crate) mesh: MeshId,
     pub(crate) position: cgmath::Vector3<f32>,
     pub(crate) rotation: cgmath::Quaternion<f32>,
-    pub(crate) start_offset: usize,
-    pub(crate) array_index: usize,
-    pub(crate) max_allowed: usize,
+    pub(crate) scale: cgmath::Vector3<f32>,
+    /// Multiplied with the vertex colors of the mesh, white keeps them as they are.
+    pub(crate) color: [f32; 3],
+    pub(crate) material: Material,
+    /// Layer of the texture array the mesh is textured with.
+    pub(crate) texture: TextureId,
+}
+
+/// How an instance responds to the scene's lights.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Material {
+    /// Strength of the specular highlight, 0 for a matte surface.
+    pub specular: f32,
+    /// Blinn-Phong exponent, higher values give smaller and sharper highlights.
+    pub shininess: f32,
+    /// Light given off regardless of the scene's lights.
+    pub emissive: [f32; 3],
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            specular: 0.5,
+            shininess: 32.0,
+            emissive: [0.0, 0.0, 0.0],
+        }
+    }
+}
+
+/// Refers to an instance added to the `InstanceHandler` by its slot within the
+/// region of its mesh, so it stays valid when regions move in the instance buffer.
+/// The generation detects handles whose slot was removed and then reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceHandle {
+    pub(crate) mesh: MeshId,
+    pub(crate) index: usize,
+    pub(crate) generation: u32,
 }
 
 pub const MAX_INSTANCES: usize = 1000000;
@@ -20,16 +52,53 @@ pub const MAX_INSTANCES: usize = 1000000;
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceRaw {
     model: [[f32; 4]; 4],
+    normal: [[f32; 3]; 3],
+    color: [f32; 3],
+    emissive: [f32; 3],
+    /// Specular strength and shininess.
+    material: [f32; 2],
+    texture: u32,
 }
 
 impl Instance {
+    /// An instance of `mesh` at `position`, unrotated, unscaled and untinted, with the
+    /// default material and no texture.
+    pub fn new(mesh: MeshId, position: cgmath::Vector3<f32>) -> Self {
+        Instance {
+            mesh,
+            position,
+            rotation: cgmath::Quaternion::from_angle_y(cgmath::Deg(0.0)),
+            scale: cgmath::Vector3::new(1.0, 1.0, 1.0),
+            color: [1.0, 1.0, 1.0],
+            material: Material::default(),
+            texture: TextureId::NONE,
+        }
+    }
+
     pub(crate) fn to_raw(&self) -> InstanceRaw {
         InstanceRaw {
             model: (cgmath::Matrix4::from_translation(self.position)
-                * cgmath::Matrix4::from(self.rotation))
+                * cgmath::Matrix4::from(self.rotation)
+                * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z))
             .into(),
+            normal: self.normal_matrix().into(),
+            color: self.color,
+            emissive: self.material.emissive,
+            material: [self.material.specular, self.material.shininess],
+            texture: self.texture.0,
         }
     }
+
+    /// The inverse transpose of the model matrix without translation. The rotation is
+    /// its own inverse transpose, so only the scale has to be inverted.
+    fn normal_matrix(&self) -> cgmath::Matrix3<f32> {
+        cgmath::Matrix3::from(self.rotation)
+            * cgmath::Matrix3::from_diagonal(cgmath::Vector3::new(
+                1.0 / self.scale.x,
+                1.0 / self.scale.y,
+                1.0 / self.scale.z,
+            ))
+    }
 }
 
 impl InstanceRaw {
@@ -67,7 +136,96 @@ impl InstanceRaw {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                // The normal matrix is a mat3, reassembled from three vec3s the same way
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 22]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 28]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 31]>() as wgpu::BufferAddress,
+                    shader_location: 14,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 33]>() as wgpu::BufferAddress,
+                    shader_location: 15,
+                    format: wgpu::VertexFormat::Uint32,
+                },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{assert_relative_eq, InnerSpace, Matrix, Matrix4, Quaternion, Rotation3, Vector3};
+
+    use super::*;
+
+    #[test]
+    fn normal_matrix_is_inverse_transpose_of_model() {
+        let instance = Instance {
+            mesh: MeshId(0),
+            position: Vector3::new(3.0, -1.0, 2.0),
+            rotation: Quaternion::from_axis_angle(
+                Vector3::new(1.0, 1.0, 0.0).normalize(),
+                cgmath::Deg(40.0),
+            ),
+            scale: Vector3::new(2.0, 0.5, 3.0),
+            color: [1.0, 1.0, 1.0],
+            material: Material::default(),
+            texture: TextureId::NONE,
+        };
+        let model = Matrix4::from(instance.to_raw().model);
+        let expected =
+            cgmath::Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate())
+                .invert()
+                .unwrap()
+                .transpose();
+
+        assert_relative_eq!(instance.normal_matrix(), expected, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn color_material_and_texture_are_packed_after_the_matrices() {
+        let instance = Instance {
+            mesh: MeshId(0),
+            position: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::from_angle_y(cgmath::Deg(0.0)),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            color: [0.25, 0.5, 1.0],
+            material: Material {
+                specular: 0.1,
+                shininess: 8.0,
+                emissive: [1.0, 0.0, 0.0],
+            },
+            texture: TextureId(3),
+        };
+        let raw = [instance.to_raw()];
+        let raw: &[f32] = bytemuck::cast_slice(&raw);
+
+        assert_eq!(raw[25..33], [0.25, 0.5, 1.0, 1.0, 0.0, 0.0, 0.1, 8.0]);
+        assert_eq!(raw[33].to_bits(), 3);
+    }
+}
@@ -1,121 +1,328 @@
 use crate::render::instance;
-use crate::render::instance::{InstanceType, MAX_INSTANCES};
+use crate::render::instance::{InstanceHandle, MAX_INSTANCES};
+use crate::render::instance_region::{InstanceRegion, INITIAL_REGION_CAPACITY};
+use crate::render::mesh::MeshId;
 use crate::Instance;
-use cgmath::{Quaternion, Rotation3, Vector3};
 use std::collections::HashMap;
 
 pub struct InstanceHandler {
-    pub(crate) instances: Vec<instance::Instance>,
-    pub(crate) instance_changes: Vec<usize>,
-    pub(crate) max_allowed_sizes: HashMap<InstanceType, usize>,
-    pub(crate) max_index: usize,
+    pub(crate) regions: HashMap<MeshId, InstanceRegion>,
+    pub(crate) region_order: Vec<MeshId>,
+    pub(crate) instance_changes: Vec<(MeshId, usize)>,
+    /// Total slots reserved by all regions, the instance buffer must hold at least this many.
+    pub(crate) capacity: usize,
+    /// Set when a region grew and `start_offset`s moved, the updater then
+    /// reallocates `instance_buffer` and copies every region to its new place.
+    pub(crate) layout_changed: bool,
     pub(crate) total_added: usize,
+    /// Regions never grow past this many slots in total.
+    pub(crate) max_instances: usize,
 }
 
 impl InstanceHandler {
     pub(crate) fn new() -> InstanceHandler {
-        let mut instances = Vec::with_capacity(MAX_INSTANCES);
-        for _ in 0..instances.capacity() {
-            instances.push(Instance {
-                instance_type: InstanceType::Empty,
-                position: Vector3 {
-                    x: (0.0),
-                    y: (0.0),
-                    z: (0.0),
-                },
-                rotation: Quaternion::from_angle_y(cgmath::Deg(2.0)),
-                start_offset: 0,
-                array_index: 0,
-                max_allowed: 0,
-            });
-        }
+        InstanceHandler::with_max_instances(MAX_INSTANCES)
+    }
 
+    pub(crate) fn with_max_instances(max_instances: usize) -> InstanceHandler {
         InstanceHandler {
-            instances,
+            regions: HashMap::new(),
+            region_order: Vec::new(),
             instance_changes: Vec::new(),
-            max_allowed_sizes: HashMap::new(),
-            max_index: 0,
+            capacity: 0,
+            layout_changed: false,
             total_added: 0,
+            max_instances,
+        }
+    }
+
+    /// Returns false once the slot behind `handle` has been removed or recycled.
+    pub fn is_valid(&self, handle: InstanceHandle) -> bool {
+        match self.regions.get(&handle.mesh) {
+            Some(region) => region.is_live(handle.index, handle.generation),
+            None => false,
         }
     }
 
-    pub fn get(&mut self, index: usize) -> &mut Instance {
-        return self.instances.get_mut(index).unwrap();
+    pub fn get(&mut self, handle: InstanceHandle) -> Option<&mut Instance> {
+        if !self.is_valid(handle) {
+            return None;
+        }
+        let region = self.regions.get_mut(&handle.mesh)?;
+        region.instances.get_mut(handle.index)?.as_mut()
     }
 
-    pub fn add(&mut self, mut instance: instance::Instance) {
-        self.max_allowed_sizes
-            .insert(instance.instance_type, instance.max_allowed);
+    pub fn add(&mut self, instance: instance::Instance) -> Option<InstanceHandle> {
+        let mesh = instance.mesh;
+        let index = match self.allocate(mesh) {
+            Some(index) => index,
+            None => {
+                println!("Could not find open slot for {:?}", mesh);
+                return None;
+            }
+        };
+
+        let region = self.regions.get_mut(&mesh).unwrap();
+        region.instances[index] = Some(instance);
+        self.instance_changes.push((mesh, index));
+
+        self.total_added += 1;
+
+        // println!("Add Took: {} Ms", now.elapsed().as_millis());
+        // println!("Total Entities: {}", self.total_added);
+
+        Some(InstanceHandle {
+            mesh,
+            index,
+            generation: region.generations[index],
+        })
+    }
 
-        let offsets = self.find_offset(instance.instance_type);
+    pub fn update(&mut self, handle: InstanceHandle) -> bool {
+        if !self.is_valid(handle) {
+            return false;
+        }
+        self.instance_changes.push((handle.mesh, handle.index));
+        true
+    }
 
-        if offsets.0.is_none() {
-            println!(
-                "Could not find open slot for {}",
-                instance.instance_type as u32
-            );
-            return;
+    /// Frees the slot behind `handle` so a later `add` of the same mesh can reuse it.
+    /// Its `InstanceRaw` is zeroed on the next update and bumping the generation
+    /// makes every outstanding handle to the slot stale.
+    pub fn remove(&mut self, handle: InstanceHandle) -> Option<Instance> {
+        if !self.is_valid(handle) {
+            return None;
         }
+        let region = self.regions.get_mut(&handle.mesh)?;
+        let removed = region.release(handle.index);
+
+        self.instance_changes.push((handle.mesh, handle.index));
+        self.total_added -= 1;
+
+        removed
+    }
 
-        let array_index = offsets.0.unwrap();
-        instance.array_index = array_index;
-        instance.start_offset = offsets.1;
+    /// Offset of a slot in `instance_buffer` under the current layout.
+    pub(crate) fn buffer_index(&self, mesh: MeshId, index: usize) -> usize {
+        self.regions[&mesh].start_offset + index
+    }
 
-        let mut o = instance.start_offset;
-        if o == 0 {
-            o = 1
+    fn allocate(&mut self, mesh: MeshId) -> Option<usize> {
+        // New mesh, its region starts after the last reserved one
+        if !self.regions.contains_key(&mesh) {
+            self.region_order.push(mesh);
+            self.regions
+                .insert(mesh, InstanceRegion::new(self.capacity, 0));
         }
 
-        if instance.array_index >= (instance.max_allowed + o) {
-            return;
+        if let Some(index) = self.regions.get_mut(&mesh).unwrap().allocate() {
+            return Some(index);
         }
 
-        std::mem::replace(&mut self.instances[instance.array_index], instance);
-        self.instance_changes.push(array_index);
+        self.grow(mesh)?;
+        self.regions.get_mut(&mesh).unwrap().allocate()
+    }
 
-        if array_index > self.max_index {
-            self.max_index = array_index;
+    /// Doubles the capacity of a full region and packs every region back to back.
+    fn grow(&mut self, mesh: MeshId) -> Option<()> {
+        let available = self.max_instances - self.capacity;
+        let region = self.regions.get_mut(&mesh).unwrap();
+        let extra = region.capacity.max(INITIAL_REGION_CAPACITY).min(available);
+        if extra == 0 {
+            return None;
         }
+        region.capacity += extra;
 
-        self.total_added += 1;
+        let mut offset = 0;
+        for mesh in self.region_order.iter() {
+            let region = self.regions.get_mut(mesh).unwrap();
+            region.start_offset = offset;
+            offset += region.capacity;
+        }
+        self.capacity = offset;
+        self.layout_changed = true;
 
-        // println!("Add Took: {} Ms", now.elapsed().as_millis());
-        // println!("Total Entities: {}", self.total_added);
+        Some(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Zero};
+
+    use super::*;
+
+    const CUBE: MeshId = MeshId(0);
+    const TRIANGLE: MeshId = MeshId(1);
 
-    pub fn update(&mut self, index: usize) {
-        self.instance_changes.push(index);
+    #[test]
+    fn regions_are_reserved_per_mesh() {
+        let mut handler = InstanceHandler::new();
+        let cube = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        let triangle = handler
+            .add(Instance::new(TRIANGLE, Vector3::zero()))
+            .unwrap();
+        let second_cube = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+
+        assert_eq!(handler.buffer_index(cube.mesh, cube.index), 0);
+        assert_eq!(handler.buffer_index(second_cube.mesh, second_cube.index), 1);
+        assert_eq!(
+            handler.buffer_index(triangle.mesh, triangle.index),
+            INITIAL_REGION_CAPACITY
+        );
+        assert_eq!(handler.regions[&CUBE].draw_range(), 0..2);
     }
 
-    fn find_open_slot(&self, start_index: usize) -> Option<usize> {
-        let mut offset = start_index;
-        loop {
-            if offset >= self.instances.len() {
-                return None;
-            }
-            let instance = self.instances.get(offset).unwrap();
-            if instance.instance_type == InstanceType::Empty {
-                return Option::Some(offset);
-            }
-            offset += 1;
+    #[test]
+    fn full_region_grows_and_moves_later_regions() {
+        let mut handler = InstanceHandler::new();
+        let first = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        handler
+            .add(Instance::new(TRIANGLE, Vector3::zero()))
+            .unwrap();
+        handler.layout_changed = false;
+
+        for _ in 1..=INITIAL_REGION_CAPACITY {
+            handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
         }
+
+        assert!(handler.layout_changed);
+        assert_eq!(handler.regions[&CUBE].capacity, INITIAL_REGION_CAPACITY * 2);
+        assert_eq!(
+            handler.regions[&TRIANGLE].start_offset,
+            INITIAL_REGION_CAPACITY * 2
+        );
+        assert_eq!(handler.capacity, INITIAL_REGION_CAPACITY * 3);
+        assert!(handler.get(first).is_some());
     }
 
-    fn find_offset(&self, instance_type: InstanceType) -> (Option<usize>, usize) {
-        let mut offset = 0;
-        loop {
-            let instance = self.instances.get(offset).unwrap();
+    #[test]
+    fn add_fails_once_max_instances_are_reserved() {
+        const MAX: usize = INITIAL_REGION_CAPACITY + 10;
+        let mut handler = InstanceHandler::with_max_instances(MAX);
+        for _ in 0..MAX {
+            handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        }
+        assert_eq!(handler.regions[&CUBE].capacity, MAX);
+        assert!(handler.add(Instance::new(CUBE, Vector3::zero())).is_none());
+        assert!(handler
+            .add(Instance::new(TRIANGLE, Vector3::zero()))
+            .is_none());
+        assert_eq!(handler.total_added, MAX);
+    }
 
-            if instance.instance_type == InstanceType::Empty {
-                return (self.find_open_slot(offset), offset);
-            }
+    #[test]
+    fn full_region_reuses_a_freed_slot_without_growing() {
+        let mut handler = InstanceHandler::new();
+        let handles: Vec<_> = (0..INITIAL_REGION_CAPACITY)
+            .map(|_| handler.add(Instance::new(CUBE, Vector3::zero())).unwrap())
+            .collect();
+        handler.layout_changed = false;
 
-            // Found a match of the same entity
-            if instance.instance_type == instance_type {
-                return (self.find_open_slot(offset), offset);
-            } else {
-                offset += instance.max_allowed;
-            }
-        }
+        handler.remove(handles[7]).unwrap();
+        let reused = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+
+        assert_eq!(reused.index, 7);
+        assert!(!handler.layout_changed);
+        assert_eq!(handler.regions[&CUBE].capacity, INITIAL_REGION_CAPACITY);
+        assert_eq!(handler.capacity, INITIAL_REGION_CAPACITY);
+    }
+
+    #[test]
+    fn removed_slot_is_reused_by_the_next_add_of_its_mesh() {
+        let mut handler = InstanceHandler::new();
+        let first = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        handler
+            .add(Instance::new(TRIANGLE, Vector3::zero()))
+            .unwrap();
+
+        assert!(handler.remove(first).is_some());
+        assert_eq!(handler.total_added, 2);
+
+        let triangle = handler
+            .add(Instance::new(TRIANGLE, Vector3::zero()))
+            .unwrap();
+        let cube = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        assert_eq!(triangle.index, 1);
+        assert_eq!(cube.index, first.index);
+        assert_eq!(handler.total_added, 4);
+    }
+
+    #[test]
+    fn removed_slot_is_emptied_and_queued_for_zeroing() {
+        let mut handler = InstanceHandler::new();
+        let handle = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        handler.instance_changes.clear();
+
+        let removed = handler.remove(handle).unwrap();
+        assert_eq!(removed.mesh, CUBE);
+        assert!(handler.regions[&CUBE].instances[handle.index].is_none());
+        assert_eq!(handler.instance_changes, vec![(CUBE, handle.index)]);
+    }
+
+    #[test]
+    fn removed_handle_stays_stale_after_its_slot_is_reused() {
+        let mut handler = InstanceHandler::new();
+        let old = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        handler.remove(old).unwrap();
+        handler.instance_changes.clear();
+
+        assert!(!handler.is_valid(old));
+        assert!(handler.get(old).is_none());
+        assert!(!handler.update(old));
+        assert!(handler.remove(old).is_none());
+        assert!(handler.instance_changes.is_empty());
+        assert_eq!(handler.total_added, 1);
+
+        let new = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        assert_eq!(new.index, old.index);
+        assert_ne!(new.generation, old.generation);
+        assert!(handler.get(old).is_none());
+        assert!(!handler.update(old));
+        assert!(handler.remove(old).is_none());
+        assert!(handler.get(new).is_some());
+        assert!(handler.update(new));
+        assert_eq!(handler.total_added, 2);
+    }
+
+    #[test]
+    fn removing_the_last_instances_shrinks_the_draw_range() {
+        let mut handler = InstanceHandler::new();
+        let handles: Vec<_> = (0..4)
+            .map(|_| handler.add(Instance::new(CUBE, Vector3::zero())).unwrap())
+            .collect();
+        assert_eq!(handler.regions[&CUBE].draw_range(), 0..4);
+
+        handler.remove(handles[3]).unwrap();
+        handler.remove(handles[2]).unwrap();
+        assert_eq!(handler.regions[&CUBE].draw_range(), 0..2);
+
+        handler.remove(handles[0]).unwrap();
+        assert_eq!(handler.regions[&CUBE].draw_range(), 0..2);
+        handler.remove(handles[1]).unwrap();
+        assert_eq!(handler.regions[&CUBE].draw_range(), 0..0);
+        assert!(handler.get(handles[3]).is_none());
+    }
+
+    /// Slots are numbered per region, so handles of different meshes share indices
+    /// and generations and only the mesh tells them apart.
+    #[test]
+    fn handle_is_rejected_when_its_slot_index_is_used_by_another_mesh() {
+        let mut handler = InstanceHandler::new();
+        let cube = handler.add(Instance::new(CUBE, Vector3::zero())).unwrap();
+        handler.remove(cube).unwrap();
+        let triangle = handler
+            .add(Instance::new(TRIANGLE, Vector3::zero()))
+            .unwrap();
+
+        assert_eq!(triangle.index, cube.index);
+        assert!(handler.get(cube).is_none());
+        assert!(!handler.update(cube));
+        assert!(handler.remove(cube).is_none());
+        assert!(handler.get(triangle).is_some());
+        assert_eq!(handler.total_added, 1);
     }
 }
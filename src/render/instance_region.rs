@@ -0,0 +1,159 @@
+use crate::render::instance::Instance;
+
+pub const INITIAL_REGION_CAPACITY: usize = 1024;
+
+/// The contiguous block of `instance_buffer` slots reserved for one mesh.
+/// Slots are handed out from the free list first and otherwise from `len`, so
+/// allocation never has to search the region. When the region is full the
+/// `InstanceHandler` grows `capacity` and moves `start_offset` of the regions after it.
+pub struct InstanceRegion {
+    pub(crate) start_offset: usize,
+    pub(crate) capacity: usize,
+    /// Ends at the highest live slot, trailing slots are dropped when released.
+    pub(crate) instances: Vec<Option<Instance>>,
+    /// Outlives trimmed slots, so handles to them stay stale when they are reused.
+    pub(crate) generations: Vec<u32>,
+    pub(crate) free_slots: Vec<usize>,
+    /// Where the region lived in `instance_buffer` at the last update, and how many
+    /// of its slots had been uploaded there.
+    pub(crate) gpu_offset: Option<usize>,
+    pub(crate) gpu_len: usize,
+}
+
+impl InstanceRegion {
+    pub(crate) fn new(start_offset: usize, capacity: usize) -> InstanceRegion {
+        InstanceRegion {
+            start_offset,
+            capacity,
+            instances: Vec::new(),
+            generations: Vec::new(),
+            free_slots: Vec::new(),
+            gpu_offset: None,
+            gpu_len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Range of instances the renderer has to draw, slots past `len` are unused.
+    pub(crate) fn draw_range(&self) -> std::ops::Range<u32> {
+        self.start_offset as u32..(self.start_offset + self.len()) as u32
+    }
+
+    pub(crate) fn is_live(&self, index: usize, generation: u32) -> bool {
+        match self.instances.get(index) {
+            Some(instance) => instance.is_some() && self.generations[index] == generation,
+            None => false,
+        }
+    }
+
+    pub(crate) fn allocate(&mut self) -> Option<usize> {
+        if let Some(index) = self.free_slots.pop() {
+            return Some(index);
+        }
+        if self.len() >= self.capacity {
+            return None;
+        }
+        self.instances.push(None);
+        if self.generations.len() < self.instances.len() {
+            self.generations.push(0);
+        }
+        Some(self.len() - 1)
+    }
+
+    pub(crate) fn release(&mut self, index: usize) -> Option<Instance> {
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        let removed = self.instances[index].take();
+
+        if index + 1 < self.len() {
+            self.free_slots.push(index);
+            return removed;
+        }
+        // The last slot shrinks the draw range down to the highest live slot
+        while let Some(None) = self.instances.last() {
+            self.instances.pop();
+        }
+        let len = self.len();
+        self.free_slots.retain(|&free| free < len);
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Zero};
+
+    use super::*;
+    use crate::render::mesh::MeshId;
+
+    #[test]
+    fn allocate_pops_freed_slots_before_appending() {
+        let mut region = InstanceRegion::new(0, 8);
+        for _ in 0..5 {
+            let index = region.allocate().unwrap();
+            region.instances[index] = Some(Instance::new(MeshId(0), Vector3::zero()));
+        }
+        region.release(1);
+        region.release(3);
+
+        assert_eq!(region.allocate(), Some(3));
+        assert_eq!(region.allocate(), Some(1));
+        assert_eq!(region.len(), 5);
+    }
+
+    #[test]
+    fn allocate_never_searches_for_empty_slots() {
+        let mut region = InstanceRegion::new(0, 8);
+        for _ in 0..4 {
+            region.allocate().unwrap();
+        }
+
+        // The slots are still empty, only released ones are handed out again
+        assert!(region.instances.iter().all(Option::is_none));
+        assert_eq!(region.allocate(), Some(4));
+    }
+
+    #[test]
+    fn full_region_without_free_slots_rejects_allocate() {
+        let mut region = InstanceRegion::new(0, 2);
+        region.allocate().unwrap();
+        region.allocate().unwrap();
+        assert_eq!(region.allocate(), None);
+
+        region.release(0);
+        assert_eq!(region.allocate(), Some(0));
+    }
+
+    #[test]
+    fn releasing_trailing_slots_shrinks_the_draw_range() {
+        let mut region = InstanceRegion::new(10, 8);
+        for _ in 0..4 {
+            let index = region.allocate().unwrap();
+            region.instances[index] = Some(Instance::new(MeshId(0), Vector3::zero()));
+        }
+        region.release(1);
+        region.release(3);
+        assert_eq!(region.draw_range(), 10..13);
+
+        region.release(2);
+        assert_eq!(region.draw_range(), 10..11);
+        assert!(region.free_slots.is_empty());
+        assert_eq!(region.allocate(), Some(1));
+    }
+
+    #[test]
+    fn trimmed_slots_keep_their_generation() {
+        let mut region = InstanceRegion::new(0, 8);
+        let index = region.allocate().unwrap();
+        region.instances[index] = Some(Instance::new(MeshId(0), Vector3::zero()));
+        region.release(index);
+        assert_eq!(region.len(), 0);
+
+        assert_eq!(region.allocate(), Some(index));
+        region.instances[index] = Some(Instance::new(MeshId(0), Vector3::zero()));
+        assert!(!region.is_live(index, 0));
+        assert!(region.is_live(index, 1));
+    }
+}
@@ -6,9 +6,64 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+/// Which instances the renderer skips before drawing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Culling {
+    /// Every instance is drawn straight from the instance buffer.
+    Disabled,
+    /// Instances outside the camera's frustum are dropped on the CPU and the rest
+    /// uploaded to a compact buffer every frame.
+    Cpu,
+    /// A compute pass culls the instances and fills indirect draw arguments, falls back
+    /// to `Cpu` on adapters without compute shaders or indirect draws.
+    Gpu,
+}
+
+/// Options picked when the render state is created.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderSettings {
+    pub depth_format: wgpu::TextureFormat,
+    pub depth_compare: wgpu::CompareFunction,
+    /// Ask for a software adapter, e.g. to render headless on machines without a GPU.
+    pub force_fallback_adapter: bool,
+    /// Simulation steps per second the update observers run at.
+    pub tick_rate: f32,
+    /// Most simulation steps run in one frame to catch up after a slow one.
+    pub max_catch_up_steps: u32,
+    pub culling: Culling,
+}
+
+impl RenderSettings {
+    /// The depth buffer is cleared to the farthest value the compare function lets through,
+    /// so reversed depth (`Greater`) clears to 0.
+    pub fn depth_clear_value(&self) -> f32 {
+        match self.depth_compare {
+            wgpu::CompareFunction::Greater | wgpu::CompareFunction::GreaterEqual => 0.0,
+            _ => 1.0,
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            depth_format: wgpu::TextureFormat::Depth32Float,
+            depth_compare: wgpu::CompareFunction::Less,
+            force_fallback_adapter: false,
+            tick_rate: 60.0,
+            max_catch_up_steps: 5,
+            culling: Culling::Gpu,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RenderStats {
     pub(crate) draw_calls: i32,
+    /// Instances drawn in the last frame, unknown when they were culled on the GPU,
+    /// and how many there are in total.
+    pub(crate) visible_instances: Option<usize>,
+    pub(crate) total_instances: usize,
 }
 
 #[repr(C)]
@@ -16,6 +71,8 @@ pub struct RenderStats {
 pub struct Vertex {
     pub(crate) position: [f32; 3],
     pub(crate) color: [f32; 3],
+    pub(crate) normal: [f32; 3],
+    pub(crate) tex_coords: [f32; 2],
 }
 
 impl Vertex {
@@ -34,6 +91,16 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
             ],
         }
     }
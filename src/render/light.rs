@@ -0,0 +1,182 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+/// Point lights past this many are ignored by the shader.
+pub const MAX_POINT_LIGHTS: usize = 4;
+
+pub struct DirectionalLight {
+    /// The direction the light travels in, e.g. straight down for a sun at noon.
+    pub direction: Vector3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+pub struct PointLight {
+    pub position: Point3<f32>,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which the light has faded out completely.
+    pub range: f32,
+}
+
+/// The lights of the scene, uploaded to the light uniform by the updater every frame.
+pub struct Lights {
+    pub ambient: [f32; 3],
+    pub directional: Option<DirectionalLight>,
+    /// Only added through `add_point_light`, which warns about lights past the limit.
+    point_lights: Vec<PointLight>,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DirectionalLightRaw {
+    direction: [f32; 3],
+    intensity: f32,
+    color: [f32; 3],
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointLightRaw {
+    position: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+}
+
+/// Matches `LightUniform` in shader.wgsl, vec3s are followed by a scalar to keep
+/// the 16 byte alignment uniforms need.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    ambient: [f32; 3],
+    point_light_count: u32,
+    directional: DirectionalLightRaw,
+    point_lights: [PointLightRaw; MAX_POINT_LIGHTS],
+}
+
+impl Lights {
+    /// Adds a point light, the shader ignores those past `MAX_POINT_LIGHTS`.
+    pub fn add_point_light(&mut self, light: PointLight) {
+        self.point_lights.push(light);
+        if self.point_lights.len() > MAX_POINT_LIGHTS {
+            log::warn!(
+                "Point light {} is ignored, only the first {} are used",
+                self.point_lights.len(),
+                MAX_POINT_LIGHTS
+            );
+        }
+    }
+
+    pub fn to_uniform(&self) -> LightUniform {
+        // A missing directional light is uploaded with no intensity
+        let directional = match &self.directional {
+            Some(light) => DirectionalLightRaw {
+                direction: light.direction.normalize().into(),
+                intensity: light.intensity,
+                color: light.color,
+                _padding: 0,
+            },
+            None => DirectionalLightRaw {
+                direction: [0.0, -1.0, 0.0],
+                intensity: 0.0,
+                color: [0.0, 0.0, 0.0],
+                _padding: 0,
+            },
+        };
+
+        let mut point_lights = [PointLightRaw {
+            position: [0.0, 0.0, 0.0],
+            range: 0.0,
+            color: [0.0, 0.0, 0.0],
+            intensity: 0.0,
+        }; MAX_POINT_LIGHTS];
+        for (raw, light) in point_lights.iter_mut().zip(self.point_lights.iter()) {
+            *raw = PointLightRaw {
+                position: light.position.into(),
+                range: light.range,
+                color: light.color,
+                intensity: light.intensity,
+            };
+        }
+
+        LightUniform {
+            ambient: self.ambient,
+            point_light_count: self.point_lights.len().min(MAX_POINT_LIGHTS) as u32,
+            directional,
+            point_lights,
+        }
+    }
+}
+
+impl Default for Lights {
+    fn default() -> Self {
+        let mut lights = Lights {
+            ambient: [0.1, 0.1, 0.1],
+            directional: Some(DirectionalLight {
+                direction: Vector3::new(-0.5, -1.0, -0.3),
+                color: [1.0, 1.0, 1.0],
+                intensity: 0.8,
+            }),
+            point_lights: Vec::new(),
+        };
+        lights.add_point_light(PointLight {
+            position: Point3::new(0.0, 10.0, 10.0),
+            color: [1.0, 0.9, 0.7],
+            intensity: 1.0,
+            range: 60.0,
+        });
+        lights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point_light(x: f32) -> PointLight {
+        PointLight {
+            position: Point3::new(x, 0.0, 0.0),
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            range: 10.0,
+        }
+    }
+
+    #[test]
+    fn uniform_layout_matches_the_shader() {
+        assert_eq!(
+            std::mem::size_of::<LightUniform>(),
+            16 + 32 + 32 * MAX_POINT_LIGHTS
+        );
+    }
+
+    #[test]
+    fn point_lights_are_clamped_and_direction_normalized() {
+        let lights = Lights {
+            ambient: [0.2, 0.2, 0.2],
+            directional: Some(DirectionalLight {
+                direction: Vector3::new(0.0, -3.0, 0.0),
+                color: [1.0, 1.0, 1.0],
+                intensity: 1.0,
+            }),
+            point_lights: (0..MAX_POINT_LIGHTS + 2)
+                .map(|i| point_light(i as f32))
+                .collect(),
+        };
+        let uniform = lights.to_uniform();
+
+        assert_eq!(uniform.point_light_count, MAX_POINT_LIGHTS as u32);
+        assert_eq!(uniform.point_lights[3].position, [3.0, 0.0, 0.0]);
+        assert_eq!(uniform.directional.direction, [0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn missing_directional_light_has_no_intensity() {
+        let lights = Lights {
+            directional: None,
+            ..Lights::default()
+        };
+        assert_eq!(lights.to_uniform().directional.intensity, 0.0);
+    }
+}
@@ -0,0 +1,127 @@
+use std::ops::Range;
+
+use cgmath::{InnerSpace, Vector3};
+
+use crate::render::culling::BoundingSphere;
+use crate::render::lib::Vertex;
+
+/// Identifies a mesh registered in the `MeshRegistry`, instances refer to their geometry by it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshId(pub(crate) usize);
+
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    /// 16-bit indices are enough unless the mesh has more vertices than they can address.
+    pub(crate) fn narrow(vertex_count: usize, indices: Vec<u32>) -> Indices {
+        if vertex_count > u16::MAX as usize + 1 {
+            Indices::U32(indices)
+        } else {
+            Indices::U16(indices.iter().map(|i| *i as u16).collect())
+        }
+    }
+
+    pub(crate) fn to_u32(&self) -> Vec<u32> {
+        match self {
+            Indices::U16(indices) => indices.iter().map(|i| *i as u32).collect(),
+            Indices::U32(indices) => indices.clone(),
+        }
+    }
+}
+
+impl From<&[u16]> for Indices {
+    fn from(indices: &[u16]) -> Self {
+        Indices::U16(indices.to_vec())
+    }
+}
+
+impl From<&[u32]> for Indices {
+    fn from(indices: &[u32]) -> Self {
+        Indices::U32(indices.to_vec())
+    }
+}
+
+impl From<Vec<u16>> for Indices {
+    fn from(indices: Vec<u16>) -> Self {
+        Indices::U16(indices)
+    }
+}
+
+impl From<Vec<u32>> for Indices {
+    fn from(indices: Vec<u32>) -> Self {
+        Indices::U32(indices)
+    }
+}
+
+/// Where a registered mesh lives inside the packed vertex and index buffers.
+pub struct Mesh {
+    pub(crate) base_vertex: i32,
+    pub(crate) index_range: Range<u32>,
+    pub(crate) bounds: BoundingSphere,
+}
+
+/// Gives vertices of meshes loaded without normals the area weighted average of the
+/// normals of the triangles sharing them.
+pub(crate) fn generate_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(vertices[triangle[i] as usize].position));
+        // Not normalized, so larger triangles weigh more
+        let normal = (b - a).cross(c - a);
+        for i in triangle {
+            normals[*i as usize] += normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        if normal.magnitude2() > 0.0 {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::assert_relative_eq;
+
+    use super::*;
+
+    fn vertex(position: [f32; 3]) -> Vertex {
+        Vertex {
+            position,
+            color: [1.0, 1.0, 1.0],
+            normal: [0.0, 0.0, 0.0],
+            tex_coords: [0.0, 0.0],
+        }
+    }
+
+    #[test]
+    fn shared_vertices_average_their_triangle_normals() {
+        // Two triangles folded along the X axis, one facing +Z and one facing +Y
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0]),
+            vertex([0.0, 1.0, 0.0]),
+            vertex([0.0, 0.0, -1.0]),
+        ];
+        generate_normals(&mut vertices, &[0, 1, 2, 0, 1, 3]);
+
+        assert_eq!(vertices[2].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(vertices[3].normal, [0.0, 1.0, 0.0]);
+        let shared = std::f32::consts::FRAC_1_SQRT_2;
+        assert_relative_eq!(
+            Vector3::from(vertices[0].normal),
+            Vector3::new(0.0, shared, shared)
+        );
+    }
+}
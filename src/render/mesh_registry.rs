@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use crate::render::culling::BoundingSphere;
+use crate::render::lib::Vertex;
+use crate::render::mesh::{Indices, Mesh, MeshId};
+
+/// Holds the geometry of every mesh registered at runtime. The updater packs it into
+/// `vertex_buffer` and `index_buffer` whenever a mesh was added since the last upload.
+pub struct MeshRegistry {
+    pub(crate) meshes: Vec<Mesh>,
+    names: HashMap<String, MeshId>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    needs_u32: bool,
+    pub(crate) changed: bool,
+}
+
+impl MeshRegistry {
+    pub(crate) fn new() -> MeshRegistry {
+        MeshRegistry {
+            meshes: Vec::new(),
+            names: HashMap::new(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            needs_u32: false,
+            changed: true,
+        }
+    }
+
+    /// Registers a mesh, its indices are relative to the first of its own vertices.
+    pub fn register<I: Into<Indices>>(
+        &mut self,
+        name: &str,
+        vertices: &[Vertex],
+        indices: I,
+    ) -> MeshId {
+        let indices = indices.into();
+        if let Indices::U32(_) = indices {
+            self.needs_u32 = true;
+        }
+
+        let id = MeshId(self.meshes.len());
+        let first_index = self.indices.len() as u32;
+        self.meshes.push(Mesh {
+            base_vertex: self.vertices.len() as i32,
+            index_range: first_index..first_index + indices.len() as u32,
+            bounds: BoundingSphere::from_vertices(vertices),
+        });
+        self.names.insert(name.to_string(), id);
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend(indices.to_u32());
+        self.changed = true;
+
+        id
+    }
+
+    pub fn get(&self, id: MeshId) -> Option<&Mesh> {
+        self.meshes.get(id.0)
+    }
+
+    pub fn find(&self, name: &str) -> Option<MeshId> {
+        self.names.get(name).copied()
+    }
+
+    /// 16-bit indices are used unless a mesh was registered with 32-bit ones.
+    pub(crate) fn index_format(&self) -> wgpu::IndexFormat {
+        if self.needs_u32 {
+            wgpu::IndexFormat::Uint32
+        } else {
+            wgpu::IndexFormat::Uint16
+        }
+    }
+
+    pub(crate) fn vertex_data(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.vertices)
+    }
+
+    /// Index data in `index_format`, padded to the 4 byte copy alignment.
+    pub(crate) fn index_data(&self) -> Vec<u8> {
+        if self.needs_u32 {
+            return bytemuck::cast_slice(&self.indices).to_vec();
+        }
+        let mut indices: Vec<u16> = self.indices.iter().map(|i| *i as u16).collect();
+        if !indices.len().is_multiple_of(2) {
+            indices.push(0);
+        }
+        bytemuck::cast_slice(&indices).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{CUBE, CUBE_INDICES, TRIANGLE, TRIANGLE_INDICES};
+
+    #[test]
+    fn meshes_are_packed_back_to_back() {
+        let mut registry = MeshRegistry::new();
+        let cube = registry.register("cube", CUBE, CUBE_INDICES);
+        let triangle = registry.register("triangle", TRIANGLE, TRIANGLE_INDICES);
+
+        assert_eq!(registry.find("triangle"), Some(triangle));
+        let cube = registry.get(cube).unwrap();
+        let triangle = registry.get(triangle).unwrap();
+        assert_eq!(cube.base_vertex, 0);
+        assert_eq!(cube.index_range, 0..CUBE_INDICES.len() as u32);
+        assert_eq!(triangle.base_vertex, CUBE.len() as i32);
+        assert_eq!(
+            triangle.index_range,
+            CUBE_INDICES.len() as u32..(CUBE_INDICES.len() + TRIANGLE_INDICES.len()) as u32
+        );
+    }
+
+    #[test]
+    fn index_data_is_padded_and_widened_when_needed() {
+        let mut registry = MeshRegistry::new();
+        registry.register("triangle", TRIANGLE, TRIANGLE_INDICES);
+        assert_eq!(registry.index_format(), wgpu::IndexFormat::Uint16);
+        assert_eq!(
+            registry.index_data().len(),
+            (TRIANGLE_INDICES.len() + 1) * 2
+        );
+
+        registry.register("wide", TRIANGLE, vec![0u32, 1, 2]);
+        assert_eq!(registry.index_format(), wgpu::IndexFormat::Uint32);
+        assert_eq!(
+            registry.index_data().len(),
+            (TRIANGLE_INDICES.len() + 3) * 4
+        );
+    }
+}
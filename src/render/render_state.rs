@@ -1,30 +1,76 @@
+use std::ops::Range;
+use std::path::Path;
+
+use image::RgbaImage;
 use winit::event::*;
 
+use crate::event::{EngineEvent, EngineEventQueue};
 use crate::input::key_state::KeyState;
 use crate::render::camera::{camera, camera_controller};
+use crate::render::capture::{capture_frame, CaptureError};
 
+use crate::render::gpu_culling::GpuCulling;
 use crate::render::instance_handler::InstanceHandler;
-use crate::render::lib::RenderStats;
+use crate::render::lib::{RenderSettings, RenderStats};
+use crate::render::light::Lights;
+use crate::render::mesh::MeshId;
+use crate::render::mesh_registry::MeshRegistry;
 use crate::render::renderer::on_render;
+use crate::render::texture::Texture;
+use crate::render::texture_registry::TextureRegistry;
 use crate::render::updater::on_update;
+use crate::time::fixed_timestep::FixedTimestep;
+use crate::time::frame_clock::FrameClock;
+
+/// Where frames are drawn, a window surface or an offscreen texture when headless.
+pub enum RenderTarget {
+    Surface(wgpu::Surface),
+    Offscreen(Texture),
+}
 
 pub struct RenderState {
-    pub surface: wgpu::Surface,
+    pub target: RenderTarget,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    pub settings: RenderSettings,
     pub render_pipeline: wgpu::RenderPipeline,
+    pub depth_texture: Texture,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
+    pub mesh_registry: MeshRegistry,
     pub camera: camera::Camera,
     pub camera_buffer: wgpu::Buffer,
     pub camera_bind_group: wgpu::BindGroup,
-    pub camera_controller: camera_controller::CameraController,
+    pub camera_controller: Box<dyn camera_controller::CameraController>,
+    pub lights: Lights,
+    pub light_buffer: wgpu::Buffer,
+    pub light_bind_group: wgpu::BindGroup,
+    pub texture_registry: TextureRegistry,
+    pub texture_array: Texture,
+    pub texture_sampler: wgpu::Sampler,
+    pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    pub texture_bind_group: wgpu::BindGroup,
     pub instance_handler: InstanceHandler,
     pub instance_buffer: wgpu::Buffer,
+    /// Holds the instances that survived culling, `draw_list` says which mesh draws
+    /// which range of it.
+    pub visible_instance_buffer: wgpu::Buffer,
+    pub(crate) visible_instance_capacity: usize,
+    pub(crate) draw_list: Vec<(MeshId, Range<u32>)>,
+    pub gpu_culling: Option<GpuCulling>,
     pub(crate) key_state: KeyState,
     pub(crate) render_stats: RenderStats,
+    pub(crate) frame_clock: FrameClock,
+    pub(crate) timestep: FixedTimestep,
+    pub(crate) engine_events: Vec<EngineEvent>,
+}
+
+impl EngineEventQueue for RenderState {
+    fn engine_events(&mut self) -> &mut Vec<EngineEvent> {
+        &mut self.engine_events
+    }
 }
 
 impl RenderState {
@@ -33,7 +79,18 @@ impl RenderState {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            self.camera.aspect = new_size.width as f32 / new_size.height as f32;
+            match &mut self.target {
+                RenderTarget::Surface(surface) => surface.configure(&self.device, &self.config),
+                RenderTarget::Offscreen(texture) => {
+                    *texture = Texture::create_render_target(&self.device, &self.config)
+                }
+            }
+            self.depth_texture = Texture::create_depth_texture(
+                &self.device,
+                &self.config,
+                self.settings.depth_format,
+            );
         }
     }
 
@@ -48,4 +105,15 @@ impl RenderState {
     pub(crate) fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         on_render(self)
     }
+
+    pub fn capture_frame(&mut self) -> Result<RgbaImage, CaptureError> {
+        capture_frame(self)
+    }
+
+    /// Captures the current frame and writes it to `path` as a PNG.
+    pub fn save_frame<P: AsRef<Path>>(&mut self, path: P) -> Result<(), CaptureError> {
+        let image = self.capture_frame()?;
+        image.save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
 }
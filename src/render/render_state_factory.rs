@@ -2,17 +2,32 @@ use cgmath::Point3;
 
 use std::mem;
 use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
+use crate::data::{CUBE, CUBE_INDICES, TRIANGLE, TRIANGLE_INDICES};
 use crate::input::key_state::KeyState;
+use crate::loader::obj::load_obj;
 use crate::render::camera::camera;
-use crate::render::camera::camera_controller::CameraController;
-use crate::render::instance::{InstanceRaw, MAX_INSTANCES};
+use crate::render::camera::orbit_camera_controller::{self, OrbitCameraController};
+use crate::render::gpu_culling::GpuCulling;
+use crate::render::instance::InstanceRaw;
 use crate::render::instance_handler::InstanceHandler;
-use crate::render::lib::{RenderStats, Vertex};
+use crate::render::instance_region::INITIAL_REGION_CAPACITY;
+use crate::render::lib::{Culling, RenderSettings, RenderStats, Vertex};
+use crate::render::light::Lights;
+use crate::render::mesh_registry::MeshRegistry;
+use crate::render::render_state::RenderTarget;
+use crate::render::texture::Texture;
+use crate::render::texture_registry::TextureRegistry;
+use crate::render::updater::{
+    create_mesh_buffers, create_texture_bind_group, create_visible_instance_buffer,
+};
+use crate::time::fixed_timestep::FixedTimestep;
+use crate::time::frame_clock::FrameClock;
 use crate::RenderState;
 
-pub async fn create_render_state(window: &Window) -> RenderState {
+pub async fn create_render_state(window: &Window, settings: RenderSettings) -> RenderState {
     let size = window.inner_size();
 
     // The instance is a handle to our GPU
@@ -20,37 +35,123 @@ pub async fn create_render_state(window: &Window) -> RenderState {
     let instance = wgpu::Instance::new(wgpu::Backends::all());
 
     let surface = unsafe { instance.create_surface(window) };
-    let adapter = instance
+    let adapter = request_adapter(&instance, Some(&surface), &settings)
+        .await
+        .unwrap();
+    let settings = supported_settings(&adapter, settings);
+    let (device, queue) = request_device(&adapter).await;
+
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: surface.get_preferred_format(&adapter).unwrap(),
+        width: size.width,
+        height: size.height,
+        present_mode: wgpu::PresentMode::Fifo,
+    };
+
+    surface.configure(&device, &config);
+
+    build_render_state(
+        device,
+        queue,
+        RenderTarget::Surface(surface),
+        config,
+        size,
+        settings,
+    )
+}
+
+/// Creates a render state that draws into an offscreen texture instead of a window,
+/// or `None` when no adapter is available (e.g. no GPU and no fallback adapter).
+pub async fn create_headless_render_state(
+    size: PhysicalSize<u32>,
+    settings: RenderSettings,
+) -> Option<RenderState> {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = request_adapter(&instance, None, &settings).await?;
+    let settings = supported_settings(&adapter, settings);
+    let (device, queue) = request_device(&adapter).await;
+
+    // Only used to describe the offscreen target, there is no surface to configure
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        width: size.width,
+        height: size.height,
+        present_mode: wgpu::PresentMode::Fifo,
+    };
+    let target = Texture::create_render_target(&device, &config);
+
+    Some(build_render_state(
+        device,
+        queue,
+        RenderTarget::Offscreen(target),
+        config,
+        size,
+        settings,
+    ))
+}
+
+async fn request_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface>,
+    settings: &RenderSettings,
+) -> Option<wgpu::Adapter> {
+    instance
         .request_adapter(&wgpu::RequestAdapterOptions {
             power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
+            compatible_surface,
+            force_fallback_adapter: settings.force_fallback_adapter,
         })
         .await
-        .unwrap();
+}
 
-    let (device, queue) = adapter
+async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    adapter
         .request_device(
             &wgpu::DeviceDescriptor {
                 label: None,
                 features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
+                // Near `MAX_INSTANCES` the instance buffer is larger than the default
+                // storage binding limit, GPU culling binds it whole
+                limits: wgpu::Limits {
+                    max_storage_buffer_binding_size: adapter
+                        .limits()
+                        .max_storage_buffer_binding_size,
+                    ..wgpu::Limits::default()
+                },
             },
             // Some(&std::path::Path::new("trace")), // Trace path
             None,
         )
         .await
-        .unwrap();
+        .unwrap()
+}
 
-    let config = wgpu::SurfaceConfiguration {
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        format: surface.get_preferred_format(&adapter).unwrap(),
-        width: size.width,
-        height: size.height,
-        present_mode: wgpu::PresentMode::Fifo,
-    };
+/// Falls back to CPU culling when the adapter can't run the culling compute pass.
+fn supported_settings(adapter: &wgpu::Adapter, settings: RenderSettings) -> RenderSettings {
+    let required = wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION;
+    if settings.culling == Culling::Gpu
+        && !adapter.get_downlevel_properties().flags.contains(required)
+    {
+        log::warn!("Adapter can't cull on the GPU, culling on the CPU instead");
+        return RenderSettings {
+            culling: Culling::Cpu,
+            ..settings
+        };
+    }
+    settings
+}
 
-    surface.configure(&device, &config);
+fn build_render_state(
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    target: RenderTarget,
+    config: wgpu::SurfaceConfiguration,
+    size: PhysicalSize<u32>,
+    settings: RenderSettings,
+) -> RenderState {
+    let depth_texture = Texture::create_depth_texture(&device, &config, settings.depth_format);
 
     let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
         label: Some("Shader"),
@@ -71,7 +172,7 @@ pub async fn create_render_state(window: &Window) -> RenderState {
         },
         up: cgmath::Vector3::unit_y(),
         aspect: config.width as f32 / config.height as f32,
-        fovy: 90.0,
+        projection: camera::Projection::Perspective { fovy: 90.0 },
         znear: 0.1,
         zfar: 500.0,
         ..camera::Camera::default()
@@ -86,7 +187,8 @@ pub async fn create_render_state(window: &Window) -> RenderState {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                // The fragment stage reads the view position for specular highlights
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -104,30 +206,116 @@ pub async fn create_render_state(window: &Window) -> RenderState {
         }],
         label: Some("camera_bind_group"),
     });
-    let camera_controller = CameraController::new(1.0);
+    let camera_controller = Box::new(OrbitCameraController::new(
+        orbit_camera_controller::DEFAULT_SPEED,
+    ));
 
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Render Pipeline Layout"),
-        bind_group_layouts: &[&camera_bind_group_layout],
-        push_constant_ranges: &[],
+    /* Create Lights */
+    let lights = Lights::default();
+    let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Light Buffer"),
+        contents: bytemuck::cast_slice(&[lights.to_uniform()]),
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
-
-    let vertex_data = vec![0; mem::size_of::<Vertex>() * MAX_INSTANCES];
-
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Vertex Buffer"),
-        contents: bytemuck::cast_slice(&vertex_data),
-        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    let light_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("light_bind_group_layout"),
+        });
+    let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &light_bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: light_buffer.as_entire_binding(),
+        }],
+        label: Some("light_bind_group"),
     });
 
-    let index_data = vec![0; mem::size_of::<u16>() * MAX_INSTANCES];
+    /* Create Textures */
+    let mut texture_registry = TextureRegistry::new();
+    for (name, file) in [("checker", "checker.png"), ("bricks", "bricks.jpg")] {
+        let path = format!("{}/assets/textures/{}", env!("CARGO_MANIFEST_DIR"), file);
+        if let Err(e) = texture_registry.load(name, &path) {
+            log::warn!("Could not load texture {}: {}", path, e);
+        }
+    }
+    let texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..wgpu::SamplerDescriptor::default()
+    });
+    let texture_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        });
+    let (texture_array, texture_bind_group) = create_texture_bind_group(
+        &device,
+        &queue,
+        &texture_bind_group_layout,
+        &texture_sampler,
+        &texture_registry,
+    );
+    texture_registry.changed = false;
 
-    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Index Buffer"),
-        contents: bytemuck::cast_slice(&index_data),
-        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Render Pipeline Layout"),
+        bind_group_layouts: &[
+            &camera_bind_group_layout,
+            &light_bind_group_layout,
+            &texture_bind_group_layout,
+        ],
+        push_constant_ranges: &[],
     });
 
+    let mut mesh_registry = MeshRegistry::new();
+    mesh_registry.register("cube", CUBE, CUBE_INDICES);
+    mesh_registry.register("triangle", TRIANGLE, TRIANGLE_INDICES);
+    match load_obj(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/assets/models/pyramid.obj"
+    )) {
+        Ok(mesh) => {
+            mesh.register(&mut mesh_registry, "pyramid");
+        }
+        Err(e) => log::warn!("Could not load pyramid model: {}", e),
+    }
+    let (vertex_buffer, index_buffer) = create_mesh_buffers(&device, &mesh_registry);
+    mesh_registry.changed = false;
+
     let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("Render Pipeline"),
         layout: Some(&render_pipeline_layout),
@@ -148,7 +336,13 @@ pub async fn create_render_state(window: &Window) -> RenderState {
             // Requires Features::CONSERVATIVE_RASTERIZATION
             conservative: false,
         },
-        depth_stencil: None,
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: settings.depth_format,
+            depth_write_enabled: true,
+            depth_compare: settings.depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
         multisample: wgpu::MultisampleState {
             count: 1,                         // 2.
             mask: !0,                         // 3.
@@ -167,34 +361,66 @@ pub async fn create_render_state(window: &Window) -> RenderState {
         }),
     });
 
-    let instance_data = vec![0; mem::size_of::<InstanceRaw>() * MAX_INSTANCES];
-
-    let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+    // Grown by the updater once the instance regions need more room
+    let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Instance Buffer"),
-        contents: bytemuck::cast_slice(&instance_data),
-        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        size: (mem::size_of::<InstanceRaw>() * INITIAL_REGION_CAPACITY) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX
+            | wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
     });
 
+    let visible_instance_buffer = create_visible_instance_buffer(&device, INITIAL_REGION_CAPACITY);
+
+    let gpu_culling = match settings.culling {
+        Culling::Gpu => Some(GpuCulling::new(&device)),
+        _ => None,
+    };
+
     let key_state = KeyState::new();
 
     let instance_handler = InstanceHandler::new();
 
     RenderState {
-        surface,
+        target,
         device,
         queue,
         config,
         size,
+        settings,
         render_pipeline,
+        depth_texture,
         vertex_buffer,
         index_buffer,
+        mesh_registry,
         camera,
         camera_bind_group,
         camera_buffer,
         camera_controller,
+        lights,
+        light_buffer,
+        light_bind_group,
+        texture_registry,
+        texture_array,
+        texture_sampler,
+        texture_bind_group_layout,
+        texture_bind_group,
         instance_buffer,
+        visible_instance_buffer,
+        visible_instance_capacity: INITIAL_REGION_CAPACITY,
+        draw_list: Vec::new(),
+        gpu_culling,
         key_state,
         instance_handler,
-        render_stats: RenderStats { draw_calls: 0 },
+        render_stats: RenderStats {
+            draw_calls: 0,
+            visible_instances: None,
+            total_instances: 0,
+        },
+        frame_clock: FrameClock::new(),
+        timestep: FixedTimestep::new(settings.tick_rate, settings.max_catch_up_steps),
+        engine_events: Vec::new(),
     }
 }
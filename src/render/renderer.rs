@@ -1,24 +1,66 @@
-use crate::data::{CUBE, CUBE_INDICES, TRIANGLE_INDICES};
-use crate::render::instance::InstanceType;
+use crate::render::gpu_culling::DrawIndexedIndirect;
+use crate::render::instance::InstanceRaw;
+use crate::render::lib::Culling;
+use crate::render::render_state::RenderTarget;
 use crate::RenderState;
 use std::iter;
+use std::mem;
+use wgpu::BufferAddress;
 
 pub fn on_render(state: &mut RenderState) -> Result<(), wgpu::SurfaceError> {
-    let output = state.surface.get_current_texture()?;
-    let view = output
-        .texture
-        .create_view(&wgpu::TextureViewDescriptor::default());
+    let (output, view) = match &state.target {
+        RenderTarget::Surface(surface) => {
+            let output = surface.get_current_texture()?;
+            let view = output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            (Some(output), view)
+        }
+        RenderTarget::Offscreen(target) => (
+            None,
+            target
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+        ),
+    };
 
     let mut encoder = state
         .device
         .create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
+    encode_scene(state, &view, &mut encoder);
+
+    state.queue.submit(iter::once(encoder.finish()));
+    if let Some(output) = output {
+        output.present();
+    }
+
+    match state.render_stats.visible_instances {
+        Some(visible) => println!(
+            "Draw Calls: {}. Visible Entities: {} of {}",
+            state.render_stats.draw_calls, visible, state.render_stats.total_instances
+        ),
+        None => println!(
+            "Draw Calls: {}. Total Entities: {}",
+            state.render_stats.draw_calls, state.render_stats.total_instances
+        ),
+    }
+
+    Ok(())
+}
+
+/// Records the render pass drawing every instance into `view`.
+pub(crate) fn encode_scene(
+    state: &mut RenderState,
+    view: &wgpu::TextureView,
+    encoder: &mut wgpu::CommandEncoder,
+) {
     {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[wgpu::RenderPassColorAttachment {
-                view: &view,
+                view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -30,68 +72,71 @@ pub fn on_render(state: &mut RenderState) -> Result<(), wgpu::SurfaceError> {
                     store: true,
                 },
             }],
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &state.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(state.settings.depth_clear_value()),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
         });
 
         render_pass.set_pipeline(&state.render_pipeline); // 2.
 
         render_pass.set_bind_group(0, &state.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &state.light_bind_group, &[]);
+        render_pass.set_bind_group(2, &state.texture_bind_group, &[]);
 
         render_pass.set_vertex_buffer(0, state.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, state.instance_buffer.slice(..));
 
-        render_pass.set_index_buffer(state.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.set_index_buffer(
+            state.index_buffer.slice(..),
+            state.mesh_registry.index_format(),
+        );
 
-        let triangle_indices = TRIANGLE_INDICES.len() as u32;
-        let cube_indices = CUBE_INDICES.len() as u32;
-        let mut offset = 0;
         state.render_stats.draw_calls = 0;
 
-        if state.instance_handler.instances.is_empty() {
-            return Ok(());
-        }
-
-        for _ in 0..state.instance_handler.max_index {
-            if offset > state.instance_handler.instances.len() - 1 {
-                break;
-            }
-
-            let instance = state.instance_handler.instances.get(offset).unwrap();
-
-            if instance.instance_type == InstanceType::Empty {
-                offset += 1;
-                continue;
-            }
-
-            let max_instances = instance.max_allowed;
-
-            if instance.instance_type == InstanceType::Cube {
-                state.render_stats.draw_calls += 1;
-                render_pass.draw_indexed(
-                    0..cube_indices,
-                    0,
-                    instance.start_offset as u32..(instance.start_offset + max_instances) as u32,
-                ); // 3.
-            } else {
-                render_pass.draw_indexed(
-                    cube_indices..cube_indices + triangle_indices,
-                    (CUBE.len() as i32) - 1,
-                    instance.start_offset as u32..(instance.start_offset + max_instances) as u32,
-                ); // 3.
+        // The culling pass filled one set of indirect arguments per entry of the draw
+        // list, each drawing from the start of its region
+        if let Some(gpu_culling) = &state.gpu_culling {
+            let raw_size = mem::size_of::<InstanceRaw>() as BufferAddress;
+            let args_size = mem::size_of::<DrawIndexedIndirect>() as BufferAddress;
+            for (i, (_, slots)) in state.draw_list.iter().enumerate() {
+                render_pass.set_vertex_buffer(
+                    1,
+                    state.visible_instance_buffer.slice(
+                        slots.start as BufferAddress * raw_size
+                            ..slots.end as BufferAddress * raw_size,
+                    ),
+                );
+                render_pass.draw_indexed_indirect(
+                    gpu_culling.indirect_buffer(),
+                    i as BufferAddress * args_size,
+                );
                 state.render_stats.draw_calls += 1;
             }
+            return;
+        }
 
-            offset += instance.max_allowed;
+        let instance_buffer = match state.settings.culling {
+            Culling::Disabled => &state.instance_buffer,
+            _ => &state.visible_instance_buffer,
+        };
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+
+        for (mesh, instances) in state.draw_list.iter() {
+            let mesh = match state.mesh_registry.get(*mesh) {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+
+            state.render_stats.draw_calls += 1;
+            render_pass.draw_indexed(
+                mesh.index_range.clone(),
+                mesh.base_vertex,
+                instances.clone(),
+            );
         }
     }
-
-    state.queue.submit(iter::once(encoder.finish()));
-    output.present();
-
-    println!(
-        "Draw Calls: {}. Total Entities: {}",
-        state.render_stats.draw_calls, state.instance_handler.total_added
-    );
-
-    Ok(())
 }
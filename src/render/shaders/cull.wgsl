@@ -0,0 +1,97 @@
+// Matches `InstanceRaw`, copied as plain words since its matrices aren't laid out
+// the way WGSL lays out matrices in storage buffers
+struct Instance {
+    data: array<u32, 34>;
+};
+
+[[block]]
+struct Instances {
+    data: array<Instance>;
+};
+
+// One per mesh drawn, `start` is where the mesh's region begins in both instance buffers
+struct CullRegion {
+    center: vec3<f32>;
+    radius: f32;
+    start: u32;
+    len: u32;
+};
+
+[[block]]
+struct CullRegions {
+    data: array<CullRegion>;
+};
+
+// The arguments of `draw_indexed_indirect`
+struct DrawIndexedIndirect {
+    index_count: u32;
+    instance_count: atomic<u32>;
+    first_index: u32;
+    base_vertex: i32;
+    first_instance: u32;
+};
+
+[[block]]
+struct Draws {
+    data: array<DrawIndexedIndirect>;
+};
+
+[[block]]
+struct Frustum {
+    planes: array<vec4<f32>, 6>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> frustum: Frustum;
+[[group(0), binding(1)]]
+var<storage, read> instances: Instances;
+[[group(0), binding(2)]]
+var<storage, read> regions: CullRegions;
+[[group(0), binding(3)]]
+var<storage, read_write> visible: Instances;
+[[group(0), binding(4)]]
+var<storage, read_write> draws: Draws;
+
+fn model_column(index: u32, column: u32) -> vec4<f32> {
+    let data = &instances.data[index].data;
+    return vec4<f32>(
+        bitcast<f32>((*data)[column * 4u]),
+        bitcast<f32>((*data)[column * 4u + 1u]),
+        bitcast<f32>((*data)[column * 4u + 2u]),
+        bitcast<f32>((*data)[column * 4u + 3u]),
+    );
+}
+
+// x is the slot within a region, y the region
+[[stage(compute), workgroup_size(64)]]
+fn cs_main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let region = regions.data[id.y];
+    if (id.x >= region.len) {
+        return;
+    }
+    let index = region.start + id.x;
+
+    let column_3 = model_column(index, 3u);
+    // Removed slots are zeroed, a live model matrix always ends in 1
+    if (column_3.w == 0.0) {
+        return;
+    }
+    let column_0 = model_column(index, 0u);
+    let column_1 = model_column(index, 1u);
+    let column_2 = model_column(index, 2u);
+    let model = mat4x4<f32>(column_0, column_1, column_2, column_3);
+
+    let center = (model * vec4<f32>(region.center, 1.0)).xyz;
+    let scale = max(length(column_0.xyz), max(length(column_1.xyz), length(column_2.xyz)));
+    let radius = region.radius * scale;
+
+    for (var i: u32 = 0u; i < 6u; i = i + 1u) {
+        let plane = frustum.planes[i];
+        if (dot(plane.xyz, center) + plane.w < -radius) {
+            return;
+        }
+    }
+
+    let slot = atomicAdd(&draws.data[id.y].instance_count, 1u);
+    visible.data[region.start + slot] = instances.data[index];
+}
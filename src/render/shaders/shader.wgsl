@@ -3,24 +3,70 @@ struct InstanceInput {
     [[location(6)]] model_matrix_1: vec4<f32>;
     [[location(7)]] model_matrix_2: vec4<f32>;
     [[location(8)]] model_matrix_3: vec4<f32>;
+    [[location(9)]] normal_matrix_0: vec3<f32>;
+    [[location(10)]] normal_matrix_1: vec3<f32>;
+    [[location(11)]] normal_matrix_2: vec3<f32>;
+    [[location(12)]] color: vec3<f32>;
+    [[location(13)]] emissive: vec3<f32>;
+    [[location(14)]] material: vec2<f32>; // specular, shininess
+    [[location(15)]] texture: u32;
 };
 
 [[block]] // 1.
 struct CameraUniform {
+    view_position: vec4<f32>;
     view_proj: mat4x4<f32>;
 };
 
 [[group(0), binding(0)]] // 2.
 var<uniform> camera: CameraUniform;
 
+struct DirectionalLight {
+    direction: vec3<f32>;
+    intensity: f32;
+    color: vec3<f32>;
+};
+
+struct PointLight {
+    position: vec3<f32>;
+    range: f32;
+    color: vec3<f32>;
+    intensity: f32;
+};
+
+[[block]]
+struct LightUniform {
+    ambient: vec3<f32>;
+    point_light_count: u32;
+    directional: DirectionalLight;
+    point_lights: array<PointLight, 4>;
+};
+
+[[group(1), binding(0)]]
+var<uniform> lights: LightUniform;
+
+// Every texture is a layer of one array, instances pick theirs by index
+[[group(2), binding(0)]]
+var t_diffuse: texture_2d_array<f32>;
+[[group(2), binding(1)]]
+var s_diffuse: sampler;
+
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] color: vec3<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] tex_coords: vec2<f32>;
 };
 
 struct VertexOutput {
     [[builtin(position)]] clip_position: vec4<f32>;
     [[location(0)]] color: vec3<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    [[location(2)]] world_position: vec3<f32>;
+    [[location(3)]] emissive: vec3<f32>;
+    [[location(4)]] material: vec2<f32>;
+    [[location(5)]] tex_coords: vec2<f32>;
+    [[location(6), interpolate(flat)]] texture: u32;
 };
 
 [[stage(vertex)]]
@@ -34,15 +80,68 @@ fn vs_main(
             instance.model_matrix_2,
             instance.model_matrix_3,
         );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
 
     var out: VertexOutput;
-    out.color = model.color;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.color = model.color * instance.color;
+    out.emissive = instance.emissive;
+    out.material = instance.material;
+    out.tex_coords = model.tex_coords;
+    out.texture = instance.texture;
+    out.world_normal = normal_matrix * model.normal;
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
+// Blinn-Phong diffuse and specular terms of one light, `light_dir` points towards the light
+fn blinn_phong(in: VertexOutput, base_color: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>, light_dir: vec3<f32>, color: vec3<f32>) -> vec3<f32> {
+    let diffuse = max(dot(normal, light_dir), 0.0);
+    let half_dir = normalize(view_dir + light_dir);
+    let specular = pow(max(dot(normal, half_dir), 0.0), in.material.y) * in.material.x;
+    return color * (diffuse * base_color + specular);
+}
+
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+    let normal = normalize(in.world_normal);
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    let texel = textureSample(t_diffuse, s_diffuse, in.tex_coords, i32(in.texture));
+    let base_color = in.color * texel.rgb;
+
+    var result = lights.ambient * base_color + in.emissive;
+
+    let directional = lights.directional;
+    result = result + blinn_phong(
+        in,
+        base_color,
+        normal,
+        view_dir,
+        -directional.direction,
+        directional.color * directional.intensity,
+    );
+
+    for (var i: u32 = 0u; i < lights.point_light_count; i = i + 1u) {
+        let light = lights.point_lights[i];
+        let to_light = light.position - in.world_position;
+        let distance = length(to_light);
+        // Fades out smoothly and reaches zero at the light's range
+        let falloff = clamp(1.0 - distance / light.range, 0.0, 1.0);
+        result = result + blinn_phong(
+            in,
+            base_color,
+            normal,
+            view_dir,
+            to_light / distance,
+            light.color * light.intensity * falloff * falloff,
+        );
+    }
+
+    return vec4<f32>(result, 1.0);
 }
- 
\ No newline at end of file
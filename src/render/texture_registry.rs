@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::{Rgba, RgbaImage};
+
+/// Width and height of every layer of the texture array, textures are scaled to fit.
+pub const TEXTURE_LAYER_SIZE: u32 = 256;
+/// The array layer count every adapter supports.
+pub const MAX_TEXTURE_LAYERS: usize = 256;
+
+/// Identifies a texture registered in the `TextureRegistry` by its layer in the texture array.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(pub(crate) u32);
+
+impl TextureId {
+    /// A plain white layer, instances using it only show their vertex and instance colors.
+    pub const NONE: TextureId = TextureId(0);
+}
+
+/// Holds every texture registered at runtime as a layer of one texture array, so
+/// instances of the same mesh can show different textures. The updater uploads the
+/// array again whenever a texture was added since the last upload.
+pub struct TextureRegistry {
+    layers: Vec<RgbaImage>,
+    names: HashMap<String, TextureId>,
+    pub(crate) changed: bool,
+}
+
+impl TextureRegistry {
+    pub(crate) fn new() -> TextureRegistry {
+        TextureRegistry {
+            layers: vec![RgbaImage::from_pixel(
+                TEXTURE_LAYER_SIZE,
+                TEXTURE_LAYER_SIZE,
+                Rgba([255, 255, 255, 255]),
+            )],
+            names: HashMap::new(),
+            changed: true,
+        }
+    }
+
+    /// Adds `image` as a new layer, or returns `TextureId::NONE` once the array is full.
+    pub fn register(&mut self, name: &str, image: &RgbaImage) -> TextureId {
+        if self.layers.len() >= MAX_TEXTURE_LAYERS {
+            log::warn!("No texture layer left for {}", name);
+            return TextureId::NONE;
+        }
+
+        let layer = if image.dimensions() == (TEXTURE_LAYER_SIZE, TEXTURE_LAYER_SIZE) {
+            image.clone()
+        } else {
+            image::imageops::resize(
+                image,
+                TEXTURE_LAYER_SIZE,
+                TEXTURE_LAYER_SIZE,
+                FilterType::Triangle,
+            )
+        };
+
+        let id = TextureId(self.layers.len() as u32);
+        self.layers.push(layer);
+        self.names.insert(name.to_string(), id);
+        self.changed = true;
+
+        id
+    }
+
+    /// Loads a PNG or JPEG file, or any other format the `image` crate can decode.
+    pub fn load<P: AsRef<Path>>(&mut self, name: &str, path: P) -> image::ImageResult<TextureId> {
+        let image = image::open(path)?.into_rgba8();
+        Ok(self.register(name, &image))
+    }
+
+    pub fn find(&self, name: &str) -> Option<TextureId> {
+        self.names.get(name).copied()
+    }
+
+    pub(crate) fn layers(&self) -> &[RgbaImage] {
+        &self.layers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!(
+            "{}/tests/fixtures/textures/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        )
+    }
+
+    #[test]
+    fn loaded_textures_are_scaled_to_a_new_layer() {
+        let mut registry = TextureRegistry::new();
+        registry.changed = false;
+
+        let id = registry.load("quad", fixture("2x2.png")).unwrap();
+
+        assert_eq!(id, TextureId(1));
+        assert_eq!(registry.find("quad"), Some(id));
+        assert!(registry.changed);
+        let layer = &registry.layers()[1];
+        assert_eq!(layer.dimensions(), (TEXTURE_LAYER_SIZE, TEXTURE_LAYER_SIZE));
+        assert_eq!(*layer.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(
+            *layer.get_pixel(TEXTURE_LAYER_SIZE - 1, TEXTURE_LAYER_SIZE - 1),
+            Rgba([255, 255, 255, 128])
+        );
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let mut registry = TextureRegistry::new();
+        assert!(registry.load("missing", fixture("missing.png")).is_err());
+        assert_eq!(registry.layers().len(), 1);
+    }
+
+    #[test]
+    fn full_array_falls_back_to_no_texture() {
+        let mut registry = TextureRegistry::new();
+        let image = RgbaImage::new(TEXTURE_LAYER_SIZE, TEXTURE_LAYER_SIZE);
+        for i in 1..MAX_TEXTURE_LAYERS {
+            assert_eq!(
+                registry.register(&i.to_string(), &image),
+                TextureId(i as u32)
+            );
+        }
+        assert_eq!(registry.register("overflow", &image), TextureId::NONE);
+    }
+}
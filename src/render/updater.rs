@@ -1,43 +1,241 @@
-use crate::data::{CUBE, CUBE_INDICES, TRIANGLE, TRIANGLE_INDICES};
+use crate::render::culling::{cull_instances, Frustum};
 use crate::render::instance::InstanceRaw;
-use crate::render::lib::Vertex;
+use crate::render::lib::Culling;
+use crate::render::mesh_registry::MeshRegistry;
+use crate::render::texture::Texture;
+use crate::render::texture_registry::TextureRegistry;
 use crate::RenderState;
+use bytemuck::Zeroable;
 use std::mem;
+use wgpu::util::DeviceExt;
 use wgpu::BufferAddress;
 
 pub fn on_update(state: &mut RenderState) {
+    state.camera.update_interpolated(state.timestep.alpha());
     state.queue.write_buffer(
         &state.camera_buffer,
         0,
         bytemuck::cast_slice(&[state.camera.uniform]),
     );
-
-    state
-        .queue
-        .write_buffer(&state.vertex_buffer, 0, bytemuck::cast_slice(CUBE));
-
     state.queue.write_buffer(
-        &state.vertex_buffer,
-        (CUBE.len() * mem::size_of::<Vertex>()) as BufferAddress,
-        bytemuck::cast_slice(TRIANGLE),
+        &state.light_buffer,
+        0,
+        bytemuck::cast_slice(&[state.lights.to_uniform()]),
     );
 
-    state
-        .queue
-        .write_buffer(&state.index_buffer, 0, bytemuck::cast_slice(CUBE_INDICES));
-    state.queue.write_buffer(
-        &state.index_buffer,
-        (CUBE_INDICES.len() * mem::size_of::<u16>()) as BufferAddress,
-        bytemuck::cast_slice(TRIANGLE_INDICES),
-    );
+    if state.mesh_registry.changed {
+        let (vertex_buffer, index_buffer) =
+            create_mesh_buffers(&state.device, &state.mesh_registry);
+        state.vertex_buffer = vertex_buffer;
+        state.index_buffer = index_buffer;
+        state.mesh_registry.changed = false;
+    }
+
+    if state.texture_registry.changed {
+        let (texture_array, texture_bind_group) = create_texture_bind_group(
+            &state.device,
+            &state.queue,
+            &state.texture_bind_group_layout,
+            &state.texture_sampler,
+            &state.texture_registry,
+        );
+        state.texture_array = texture_array;
+        state.texture_bind_group = texture_bind_group;
+        state.texture_registry.changed = false;
+    }
+
+    sync_instance_buffer(state);
+    update_draw_list(state);
+}
+
+/// Packs every registered mesh into fresh vertex and index buffers.
+pub(crate) fn create_mesh_buffers(
+    device: &wgpu::Device,
+    mesh_registry: &MeshRegistry,
+) -> (wgpu::Buffer, wgpu::Buffer) {
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Vertex Buffer"),
+        contents: mesh_registry.vertex_data(),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Index Buffer"),
+        contents: &mesh_registry.index_data(),
+        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+    });
+
+    (vertex_buffer, index_buffer)
+}
+
+/// Uploads every registered texture into a fresh texture array and binds it with `sampler`.
+pub(crate) fn create_texture_bind_group(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    texture_registry: &TextureRegistry,
+) -> (Texture, wgpu::BindGroup) {
+    let texture_array = Texture::create_texture_array(device, queue, texture_registry.layers());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture_array.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some("texture_bind_group"),
+    });
+
+    (texture_array, bind_group)
+}
+
+/// Writes changed instances to `instance_buffer`. If regions were moved since the
+/// last update, the buffer is reallocated first and every region's uploaded
+/// instances are copied over to its new `start_offset` on the GPU.
+fn sync_instance_buffer(state: &mut RenderState) {
+    let raw_size = mem::size_of::<InstanceRaw>();
+    let handler = &mut state.instance_handler;
+
+    if handler.layout_changed {
+        let instance_buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (handler.capacity * raw_size) as BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
 
-    while let Some(index) = state.instance_handler.instance_changes.pop() {
-        let instance = state.instance_handler.instances.get(index).unwrap();
-        let raw = instance.to_raw();
+        let mut encoder = state
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Instance Relocation Encoder"),
+            });
+        for region in handler.regions.values() {
+            if let Some(gpu_offset) = region.gpu_offset {
+                if region.gpu_len == 0 {
+                    continue;
+                }
+                encoder.copy_buffer_to_buffer(
+                    &state.instance_buffer,
+                    (gpu_offset * raw_size) as BufferAddress,
+                    &instance_buffer,
+                    (region.start_offset * raw_size) as BufferAddress,
+                    (region.gpu_len * raw_size) as BufferAddress,
+                );
+            }
+        }
+        state.queue.submit(std::iter::once(encoder.finish()));
+
+        state.instance_buffer = instance_buffer;
+        handler.layout_changed = false;
+    }
+
+    while let Some((mesh, index)) = handler.instance_changes.pop() {
+        // Removed slots are zeroed so they collapse to nothing when drawn, slots
+        // trimmed from the end of a region are zeroed as well
+        let raw = match handler.regions[&mesh]
+            .instances
+            .get(index)
+            .and_then(Option::as_ref)
+        {
+            Some(instance) => instance.to_raw(),
+            None => InstanceRaw::zeroed(),
+        };
         state.queue.write_buffer(
             &state.instance_buffer,
-            (index * mem::size_of::<InstanceRaw>()) as BufferAddress,
+            (handler.buffer_index(mesh, index) * raw_size) as BufferAddress,
             bytemuck::cast_slice(&[raw]),
         );
     }
+
+    for region in handler.regions.values_mut() {
+        region.gpu_offset = Some(region.start_offset);
+        region.gpu_len = region.len();
+    }
+}
+
+/// Decides which instances the renderer draws this frame. With culling the visible
+/// ones are uploaded to `visible_instance_buffer`, which grows to fit them.
+fn update_draw_list(state: &mut RenderState) {
+    let handler = &state.instance_handler;
+    state.render_stats.total_instances = handler.total_added;
+
+    match state.settings.culling {
+        Culling::Disabled => {
+            state.draw_list = handler
+                .region_order
+                .iter()
+                .map(|mesh| (*mesh, handler.regions[mesh].draw_range()))
+                .filter(|(_, range)| !range.is_empty())
+                .collect();
+            state.render_stats.visible_instances = Some(handler.total_added);
+        }
+        Culling::Gpu => {
+            // Visible instances stay in their region's slots, so the buffers match in size
+            if handler.capacity > state.visible_instance_capacity {
+                state.visible_instance_buffer =
+                    create_visible_instance_buffer(&state.device, handler.capacity);
+                state.visible_instance_capacity = handler.capacity;
+            }
+
+            let frustum = Frustum::from_view_proj(&state.camera.uniform.view_proj.into());
+            let gpu_culling = state
+                .gpu_culling
+                .as_mut()
+                .expect("GPU culling is set up with Culling::Gpu");
+            state.draw_list = gpu_culling.cull(
+                &state.device,
+                &state.queue,
+                &frustum,
+                handler,
+                &state.mesh_registry,
+                &state.instance_buffer,
+                &state.visible_instance_buffer,
+            );
+            state.render_stats.visible_instances = None;
+        }
+        Culling::Cpu => {
+            let frustum = Frustum::from_view_proj(&state.camera.uniform.view_proj.into());
+            let visible = cull_instances(handler, &state.mesh_registry, &frustum);
+
+            if visible.raws.len() > state.visible_instance_capacity {
+                let capacity = visible.raws.len().next_power_of_two();
+                state.visible_instance_buffer =
+                    create_visible_instance_buffer(&state.device, capacity);
+                state.visible_instance_capacity = capacity;
+            }
+            if !visible.raws.is_empty() {
+                state.queue.write_buffer(
+                    &state.visible_instance_buffer,
+                    0,
+                    bytemuck::cast_slice(&visible.raws),
+                );
+            }
+
+            state.render_stats.visible_instances = Some(visible.raws.len());
+            state.draw_list = visible.draws;
+        }
+    }
+}
+
+pub(crate) fn create_visible_instance_buffer(
+    device: &wgpu::Device,
+    capacity: usize,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Visible Instance Buffer"),
+        size: (capacity * mem::size_of::<InstanceRaw>()) as BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX
+            | wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
 }
@@ -1 +1,2 @@
+#[allow(dead_code)]
 pub const ROTATION_SPEED: f32 = 2.0 * std::f32::consts::PI / 60.0;
@@ -0,0 +1,32 @@
+//! Helpers shared by the tests of several modules.
+
+use winit::dpi::PhysicalSize;
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+use crate::render::lib::RenderSettings;
+use crate::render::render_state_factory::create_headless_render_state;
+use crate::RenderState;
+
+#[allow(deprecated)] // KeyboardInput can only be built with its deprecated modifiers
+pub fn key(code: VirtualKeyCode, state: ElementState) -> KeyboardInput {
+    KeyboardInput {
+        scancode: 0,
+        state,
+        virtual_keycode: Some(code),
+        modifiers: winit::event::ModifiersState::empty(),
+    }
+}
+
+/// A small headless render state on the fallback adapter, tests using it are ignored
+/// by default like the golden image tests.
+pub fn headless_render_state() -> RenderState {
+    let settings = RenderSettings {
+        force_fallback_adapter: true,
+        ..RenderSettings::default()
+    };
+    pollster::block_on(create_headless_render_state(
+        PhysicalSize::new(64, 64),
+        settings,
+    ))
+    .expect("No fallback adapter")
+}
@@ -0,0 +1,2 @@
+pub mod fixed_timestep;
+pub mod frame_clock;
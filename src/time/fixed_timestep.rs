@@ -0,0 +1,80 @@
+/// Splits the time between frames into simulation steps of equal length, so updates
+/// behave the same at any frame rate. Time left over from a frame carries over to the
+/// next, `alpha` tells how far the renderer is between the last two steps.
+pub struct FixedTimestep {
+    step: f32,
+    max_steps: u32,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    /// Runs `tick_rate` steps per second, and at most `max_steps` to catch up per frame.
+    pub fn new(tick_rate: f32, max_steps: u32) -> Self {
+        FixedTimestep {
+            step: 1.0 / tick_rate,
+            max_steps,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Seconds simulated per step.
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    /// Adds a frame's `delta` seconds and returns how many steps to run for it. When the
+    /// simulation falls too far behind, the steps past `max_steps` are dropped so a slow
+    /// frame can't cause an even slower one.
+    pub fn advance(&mut self, delta: f32) -> u32 {
+        self.accumulator += delta;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            if steps == self.max_steps {
+                log::debug!("Simulation is behind, dropping {}s", self.accumulator);
+                self.accumulator %= self.step;
+                break;
+            }
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// How far into the next step the leftover time reaches, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leftover_time_carries_over() {
+        let mut timestep = FixedTimestep::new(10.0, 5);
+
+        assert_eq!(timestep.advance(0.05), 0);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-5);
+        assert_eq!(timestep.advance(0.08), 1);
+        assert!((timestep.alpha() - 0.3).abs() < 1e-5);
+    }
+
+    #[test]
+    fn one_step_per_frame_at_the_tick_rate() {
+        let mut timestep = FixedTimestep::new(60.0, 5);
+        for _ in 0..120 {
+            assert_eq!(timestep.advance(1.0 / 60.0), 1);
+        }
+    }
+
+    #[test]
+    fn catch_up_is_limited() {
+        let mut timestep = FixedTimestep::new(10.0, 3);
+
+        assert_eq!(timestep.advance(1.05), 3);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-3);
+        assert_eq!(timestep.advance(0.0), 0);
+    }
+}
@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+/// Longest frame passed on to the update observers. A stall (a breakpoint, dragging
+/// the window) would otherwise move everything by the whole pause at once.
+const MAX_DELTA: Duration = Duration::from_millis(250);
+
+/// Measures the time between frames so motion can be expressed in units per second.
+pub struct FrameClock {
+    last_tick: Option<Instant>,
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        FrameClock { last_tick: None }
+    }
+
+    /// Returns the seconds since the previous tick, zero on the first one.
+    pub fn tick(&mut self) -> f32 {
+        self.tick_at(Instant::now())
+    }
+
+    fn tick_at(&mut self, now: Instant) -> f32 {
+        let delta = match self.last_tick {
+            Some(last_tick) => now.saturating_duration_since(last_tick).min(MAX_DELTA),
+            None => Duration::ZERO,
+        };
+        self.last_tick = Some(now);
+        delta.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_the_time_between_ticks() {
+        let start = Instant::now();
+        let mut clock = FrameClock::new();
+
+        assert_eq!(clock.tick_at(start), 0.0);
+        assert_eq!(clock.tick_at(start + Duration::from_millis(16)), 0.016);
+        assert_eq!(clock.tick_at(start + Duration::from_millis(48)), 0.032);
+    }
+
+    #[test]
+    fn long_stalls_are_clamped() {
+        let start = Instant::now();
+        let mut clock = FrameClock::new();
+        clock.tick_at(start);
+
+        assert_eq!(
+            clock.tick_at(start + Duration::from_secs(5)),
+            MAX_DELTA.as_secs_f32()
+        );
+    }
+}